@@ -4,5 +4,317 @@ extern crate test;
 #[macro_use]
 mod framework;
 
+#[allow(unused_imports)]
 use framework::*;
 
+
+// Chunk switching dominates with this many chunks,
+// since each holds only a few elements.
+tests! {
+    [@(#candy_cane_stream)(no_threads), 1024, iter_streaming_mut, 65536, cc_claim_1024_chunks],
+    [@(#candy_cane_stream)(no_threads), 4096, iter_streaming_mut, 65536, cc_claim_4096_chunks],
+    [@(#candy_cane_stream)(#8), 1024, iter_streaming_mut, 65536, cc_claim_1024_chunks_8_threads],
+    [@(#candy_cane_stream)(#8), 4096, iter_streaming_mut, 65536, cc_claim_4096_chunks_8_threads],
+}
+
+/// Claims every one of `chunks` chunks of 65536 elements once on
+/// each of `threads` threads, with nothing of the cane around it
+/// but the chunk locks, so that `iterate` is all that differs.
+///
+/// The top `held` chunks, where both start looking, are held
+/// throughout, as if by other iterators. Those are left out, so
+/// that nobody waits for them, but still have to be skipped.
+fn claim_bench<I>(b: &mut Bencher, chunks: usize, threads: usize, held: usize, iterate: I)
+where
+    I: Fn(&[parking_lot::Mutex<()>], usize, &dyn Fn(usize)) + Sync,
+{
+    assert!(held == 0 || threads == 1, "others could end up waiting for the held chunks");
+    let data = make_data::<65536>();
+    let chunk_len = data.len() / chunks;
+    let locks = (0..chunks).map(|_| parking_lot::Mutex::new(())).collect::<Vec<_>>();
+    let _held = locks[chunks - held..].iter().map(|x| x.lock()).collect::<Vec<_>>();
+    let visit = |chunk: usize| {
+        for val in &data[chunk * chunk_len..(chunk + 1) * chunk_len] {
+            black_box(*val);
+        }
+    };
+
+    b.iter(|| {
+        std::thread::scope(|s| {
+            for _ in 1..threads {
+                s.spawn(|| iterate(&locks, chunks - held, &visit));
+            }
+            iterate(&locks, chunks - held, &visit);
+        });
+    });
+}
+
+/// How iterators claimed chunks before the bitmaps: every chunk
+/// left is tried in reverse, and taken out of the list once it's
+/// locked. If all of them are taken, it waits on the last one.
+fn linear_scan_bench(b: &mut Bencher, chunks: usize, threads: usize, held: usize) {
+    claim_bench(b, chunks, threads, held, |locks, claims, visit| {
+        let mut to_visit = (0..chunks).collect::<Vec<_>>();
+        for _ in 0..claims {
+            let free = (0..to_visit.len()).rev().find_map(|index| Some((index, locks[to_visit[index]].try_lock()?)));
+            let (chunk, _guard) = match free {
+                Some((index, guard)) => (to_visit.remove(index), guard),
+                None => {
+                    let chunk = to_visit.pop().unwrap();
+                    (chunk, locks[chunk].lock())
+                }
+            };
+            visit(chunk);
+        }
+    });
+}
+
+/// How iterators claim chunks now, under `ReverseScan`: only
+/// chunks which the board doesn't have as locked are tried.
+fn bitmaps_bench(b: &mut Bencher, chunks: usize, threads: usize, held: usize) {
+    let board = candy_cane::raw::Board::new(chunks);
+    (chunks - held..chunks).for_each(|chunk| board.locked(chunk));
+
+    claim_bench(b, chunks, threads, held, |locks, claims, visit| {
+        let mut wanted = candy_cane::raw::Wanted::default();
+        wanted.reset(0..chunks);
+        for _ in 0..claims {
+            let mut below = usize::MAX;
+            let free = loop {
+                let chunk = match wanted.last_free(&board, below) {
+                    Some(chunk) => chunk,
+                    None => break None,
+                };
+                match locks[chunk].try_lock() {
+                    Some(guard) => break Some((chunk, guard)),
+                    None => below = chunk,
+                }
+            };
+            let (chunk, _guard) = free.unwrap_or_else(|| {
+                let chunk = wanted.first_wanted(0).unwrap();
+                (chunk, locks[chunk].lock())
+            });
+            wanted.remove(chunk);
+            board.locked(chunk);
+            visit(chunk);
+            board.unlocked(chunk);
+        }
+    });
+}
+
+#[bench]
+fn claim_linear_scan_1024_chunks(b: &mut Bencher) {
+    linear_scan_bench(b, 1024, 1, 0);
+}
+
+#[bench]
+fn claim_bitmaps_1024_chunks(b: &mut Bencher) {
+    bitmaps_bench(b, 1024, 1, 0);
+}
+
+#[bench]
+fn claim_linear_scan_4096_chunks(b: &mut Bencher) {
+    linear_scan_bench(b, 4096, 1, 0);
+}
+
+#[bench]
+fn claim_bitmaps_4096_chunks(b: &mut Bencher) {
+    bitmaps_bench(b, 4096, 1, 0);
+}
+
+#[bench]
+fn claim_linear_scan_1024_chunks_8_threads(b: &mut Bencher) {
+    linear_scan_bench(b, 1024, 8, 0);
+}
+
+#[bench]
+fn claim_bitmaps_1024_chunks_8_threads(b: &mut Bencher) {
+    bitmaps_bench(b, 1024, 8, 0);
+}
+
+#[bench]
+fn claim_linear_scan_4096_chunks_8_threads(b: &mut Bencher) {
+    linear_scan_bench(b, 4096, 8, 0);
+}
+
+#[bench]
+fn claim_bitmaps_4096_chunks_8_threads(b: &mut Bencher) {
+    bitmaps_bench(b, 4096, 8, 0);
+}
+
+#[bench]
+fn claim_linear_scan_1024_chunks_half_held(b: &mut Bencher) {
+    linear_scan_bench(b, 1024, 1, 512);
+}
+
+#[bench]
+fn claim_bitmaps_1024_chunks_half_held(b: &mut Bencher) {
+    bitmaps_bench(b, 1024, 1, 512);
+}
+
+#[bench]
+fn claim_linear_scan_4096_chunks_half_held(b: &mut Bencher) {
+    linear_scan_bench(b, 4096, 1, 2048);
+}
+
+#[bench]
+fn claim_bitmaps_4096_chunks_half_held(b: &mut Bencher) {
+    bitmaps_bench(b, 4096, 1, 2048);
+}
+
+/// Eight threads started together, each iterating the whole cane.
+fn policy_bench(b: &mut Bencher, policy: impl candy_cane::ChunkPolicy) {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 64>::from_vec(make_data::<65536>());
+    cane.set_chunk_policy(policy);
+
+    b.iter(|| {
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let mut iter = cane.iter_streaming_mut(..);
+                    while let Some(val) = iter.next() {
+                        black_box(*val);
+                    }
+                });
+            }
+        });
+    });
+}
+
+#[bench]
+fn cc_policy_reverse_scan(b: &mut Bencher) {
+    policy_bench(b, candy_cane::ReverseScan);
+}
+
+#[bench]
+fn cc_policy_round_robin(b: &mut Bencher) {
+    policy_bench(b, candy_cane::RoundRobin::default());
+}
+
+#[bench]
+fn cc_policy_thread_hashed(b: &mut Bencher) {
+    policy_bench(b, candy_cane::ThreadHashed);
+}
+
+#[bench]
+fn cc_policy_sticky(b: &mut Bencher) {
+    policy_bench(b, candy_cane::Sticky::default());
+}
+
+// Neighbouring chunks are locked and released all the time, so
+// these are dominated by traffic on the chunks' lock state.
+tests! {
+    [@(#candy_cane_stream)(#8), 64, iter_streaming_mut, 8192, cc_lock_traffic_64_chunks_8_threads],
+    [@(#candy_cane_stream)(#8), 256, iter_streaming_mut, 8192, cc_lock_traffic_256_chunks_8_threads],
+}
+
+// Whole batches at a time, and no locking at all, against
+// `cc_no_threads_iter_streaming_*` and `vec_no_threads_iter_*`.
+tests! {
+    [@(#candy_cane_slices)(no_threads), 1, iter_streaming, 1000, cc_no_threads_slices_1_1000],
+    [@(#candy_cane_slices)(no_threads), 1, iter_streaming, 50000, cc_no_threads_slices_1_50000],
+    [@(#candy_cane_exclusive)(no_threads), 1, 1000, cc_no_threads_exclusive_1_1000],
+    [@(#candy_cane_exclusive)(no_threads), 1, 50000, cc_no_threads_exclusive_1_50000],
+}
+
+/// Many short iterations, like one per frame, where
+/// starting the iterator is a good part of the cost.
+fn short_iterations(b: &mut Bencher, mut iterate: impl FnMut(&RawCandyCane<RawRwLock, RawMutex, usize, 64>)) {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 64>::from_vec(make_data::<1024>());
+
+    b.iter(|| {
+        for _ in 0..100 {
+            iterate(&cane);
+        }
+    });
+}
+
+#[bench]
+fn cc_short_iterations(b: &mut Bencher) {
+    short_iterations(b, |cane| {
+        let mut iter = cane.iter_streaming(100..132);
+        while let Some(val) = iter.next() {
+            black_box(*val);
+        }
+    });
+}
+
+#[bench]
+fn cc_short_iterations_reused_state(b: &mut Bencher) {
+    let mut state = candy_cane::IterState::new();
+    short_iterations(b, |cane| {
+        let mut iter = cane.iter_streaming_with(&mut state, 100..132);
+        while let Some(val) = iter.next() {
+            black_box(*val);
+        }
+    });
+}
+
+/// Appending one at a time while another thread keeps iterating.
+fn append_bench(b: &mut Bencher, mut append: impl FnMut(&RawCandyCane<RawRwLock, RawMutex, usize, 64>, usize)) {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 64>::from_vec(make_data::<1024>());
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                let mut iter = cane.iter_streaming(..1024);
+                while let Some(val) = iter.next() {
+                    black_box(*val);
+                }
+            }
+        });
+
+        b.iter(|| {
+            cane.reserve(1000);
+            for i in 0..1000 {
+                append(&cane, i);
+            }
+            cane.write().truncate(1024);
+        });
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+    });
+}
+
+#[bench]
+fn cc_append_push(b: &mut Bencher) {
+    append_bench(b, |cane, i| cane.push(i));
+}
+
+#[bench]
+fn cc_append_through_write(b: &mut Bencher) {
+    append_bench(b, |cane, i| cane.write().push(i));
+}
+
+/// Growing a fresh cane one push at a time, without reserving.
+fn growth_bench<S: Storage>(b: &mut Bencher) {
+    b.iter(|| {
+        let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 8, S>::new();
+        for i in 0..4096 {
+            cane.push(i);
+        }
+        black_box(cane);
+    });
+}
+
+#[bench]
+fn cc_growth_contiguous(b: &mut Bencher) {
+    growth_bench::<Contiguous>(b);
+}
+
+#[bench]
+fn cc_growth_segmented(b: &mut Bencher) {
+    growth_bench::<Segmented>(b);
+}
+
+/// `write()` only changing values, which leaves every chunk
+/// where it was.
+#[bench]
+fn cc_write_values_only(b: &mut Bencher) {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 64>::from_vec(make_data::<1024>());
+
+    b.iter(|| {
+        let mut guard = cane.write();
+        guard[black_box(17)] += 1;
+    });
+}
@@ -2,7 +2,7 @@
 #![feature(test)]
 extern crate test;
 pub use test::{Bencher, black_box};
-pub use candy_cane::RawCandyCane;
+pub use candy_cane::{Contiguous, RawCandyCane, Segmented, Storage};
 pub use parking_lot::{RawRwLock, RawMutex};
 pub use std::sync::Arc;
 pub use parking_lot::RwLock;
@@ -24,7 +24,7 @@ macro_rules! create_test {
         //     b.iter(|| {
         //         let cane = black_box(&cane);
         //         cane.$iter_func(..)
-        //             .for_each(|val| drop(black_box(*val)));
+        //             .for_each(|val| { black_box(*val); });
         //     });
         // }
     };
@@ -47,6 +47,39 @@ macro_rules! create_test {
         }
     };
 
+    (@(#candy_cane_slices)(no_threads), $chunks:literal, $iter_func:ident, $datalen:literal, $name:ident) => {
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            let data = make_data::<$datalen>();
+
+            let cane = RawCandyCane::<RawRwLock, RawMutex, usize, $chunks>::from_vec(data);
+
+            b.iter(|| {
+                let cane = black_box(&cane);
+                let mut iter = cane.$iter_func(..);
+
+                while let Some(slice) = iter.next_slice() {
+                    slice.iter().for_each(|val| { black_box(*val); });
+                }
+            });
+        }
+    };
+
+    (@(#candy_cane_exclusive)(no_threads), $chunks:literal, $datalen:literal, $name:ident) => {
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            let data = make_data::<$datalen>();
+
+            let mut cane = RawCandyCane::<RawRwLock, RawMutex, usize, $chunks>::from_vec(data);
+
+            b.iter(|| {
+                let cane = black_box(&mut cane);
+                cane.iter_mut_exclusive()
+                    .for_each(|val| { black_box(*val); });
+            });
+        }
+    };
+
     (@(#candy_cane_iter)(#$threads:expr), $chunks:literal, $iter_func:ident, $datalen:literal, $name:ident) => {
         // #[bench]
         // fn $name(b: &mut Bencher) {
@@ -64,7 +97,7 @@ macro_rules! create_test {
         //             thread_handles.push(std::thread::spawn(
         //                 move || {
         //                     cane.$iter_func(..)
-        //                         .for_each(|x| drop(black_box(*x)));
+        //                         .for_each(|x| { black_box(*x); });
         //                 }
         //             ));
         //         }
@@ -116,7 +149,7 @@ macro_rules! create_test {
             b.iter(|| {
                 let data = black_box(&mut data);
                 data.$iter_func()
-                    .for_each(|val| drop(black_box(*val)));
+                    .for_each(|val| { black_box(*val); });
             });
         }
     };
@@ -140,7 +173,7 @@ macro_rules! create_test {
                             let guard = data$($acquire_func)*;
 
                             guard.iter()
-                                .for_each(|x| drop(black_box(*x)));
+                                .for_each(|x| { black_box(*x); });
                         }
                     ));
                 }
@@ -173,7 +206,7 @@ macro_rules! create_test_criterion {
         //     b.iter(|| {
         //         let cane = black_box(&cane);
         //         cane.$iter_func(..)
-        //             .for_each(|val| drop(black_box(*val)));
+        //             .for_each(|val| { black_box(*val); });
         //     });
         // }
     };
@@ -212,7 +245,7 @@ macro_rules! create_test_criterion {
         //             thread_handles.push(std::thread::spawn(
         //                 move || {
         //                     cane.$iter_func(..)
-        //                         .for_each(|x| drop(black_box(*x)));
+        //                         .for_each(|x| { black_box(*x); });
         //                 }
         //             ));
         //         }
@@ -262,7 +295,7 @@ macro_rules! create_test_criterion {
             c.bench_function(stringify!($name), |b| b.iter(|| {
                 let data = black_box(&mut data);
                 data.$iter_func()
-                    .for_each(|val| drop(black_box(*val)));
+                    .for_each(|val| { black_box(*val); });
             }));
         }
     };
@@ -285,7 +318,7 @@ macro_rules! create_test_criterion {
                             let guard = data$($acquire_func)*;
 
                             guard.iter()
-                                .for_each(|x| drop(black_box(*x)));
+                                .for_each(|x| { black_box(*x); });
                         }
                     ));
                 }
@@ -0,0 +1,166 @@
+//! Picks how many chunks a cane should be split into, from
+//! how contended its chunks were since it was last split.
+//!
+//! Every chunk counts how often it was taken, how often an
+//! iterator had to block on it, and for how long it was held.
+//! Once they've been taken often enough, those counts decide
+//! whether the cane is split further, merged, or left as is,
+//! the next time it's locked exclusively. That's whenever a
+//! write guard is dropped, or once an iterator has finished,
+//! whenever the cane is next locked while nobody else holds it.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Settings for [`RawCandyCane::set_adaptive`].
+///
+/// [`RawCandyCane::set_adaptive`]: crate::RawCandyCane::set_adaptive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Adaptive {
+    /// Never merge below this many chunks.
+    pub min_chunks: usize,
+    /// Never split beyond this many chunks.
+    pub max_chunks: usize,
+    /// How many times chunks must be taken before the
+    /// counts are trusted enough to act on.
+    pub window: u64,
+    /// Double the chunks when more than one in this many
+    /// chunk acquisitions had to block.
+    pub split_one_in: u64,
+    /// Halve the chunks when nothing blocked, and chunks were
+    /// held for less than this on average, since then the
+    /// cost of switching chunks dominates.
+    pub merge_below: Duration,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            min_chunks: 1,
+            max_chunks: 1024,
+            window: 256,
+            split_one_in: 8,
+            merge_below: Duration::from_micros(2),
+        }
+    }
+}
+
+/// What one chunk has seen since the last decision.
+#[derive(Default)]
+pub(crate) struct Window {
+    acquisitions: AtomicU64,
+    blocked: AtomicU64,
+    hold_nanos: AtomicU64,
+}
+
+impl Window {
+    pub(crate) fn acquired(&self, blocked: bool) -> Instant {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if blocked {
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+        }
+        Instant::now()
+    }
+
+    pub(crate) fn released(&self, acquired: Instant) {
+        let held = acquired.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.hold_nanos.fetch_add(held, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> Totals {
+        Totals {
+            acquisitions: self.acquisitions.swap(0, Ordering::Relaxed),
+            blocked: self.blocked.swap(0, Ordering::Relaxed),
+            hold_nanos: self.hold_nanos.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    fn acquisitions(&self) -> u64 {
+        self.acquisitions.load(Ordering::Relaxed)
+    }
+}
+
+/// The windows of every chunk added together.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Totals {
+    pub(crate) acquisitions: u64,
+    pub(crate) blocked: u64,
+    pub(crate) hold_nanos: u64,
+}
+
+impl Adaptive {
+    /// Returns the chunk count to use from now on, or `None`
+    /// if there isn't enough to go on yet. Clears the windows
+    /// whenever a decision is made.
+    pub(crate) fn decide<'a>(&self, current: usize, len: usize, windows: impl Iterator<Item = &'a Window> + Clone) -> Option<usize> {
+        if !self.due(windows.clone()) {
+            return None;
+        }
+
+        let totals = windows.fold(Totals::default(), |sum, window| {
+            let totals = window.take();
+            Totals {
+                acquisitions: sum.acquisitions + totals.acquisitions,
+                blocked: sum.blocked + totals.blocked,
+                hold_nanos: sum.hold_nanos + totals.hold_nanos,
+            }
+        });
+
+        Some(self.chunks_for(current, len, totals))
+    }
+
+    /// Whether `windows` have seen enough to decide on.
+    pub(crate) fn due<'a>(&self, windows: impl Iterator<Item = &'a Window>) -> bool {
+        windows.map(Window::acquisitions).sum::<u64>() >= self.window.max(1)
+    }
+
+    pub(crate) fn chunks_for(&self, current: usize, len: usize, totals: Totals) -> usize {
+        let mean_hold = totals.hold_nanos / totals.acquisitions.max(1);
+
+        let target = if totals.blocked.saturating_mul(self.split_one_in) > totals.acquisitions {
+            // Splitting past one element per chunk can't help.
+            current.saturating_mul(2).min(len.max(current))
+        } else if totals.blocked == 0 && u128::from(mean_hold) < self.merge_below.as_nanos() {
+            current / 2
+        } else {
+            current
+        };
+
+        target.clamp(self.min_chunks.max(1), self.max_chunks.max(self.min_chunks).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adaptive, Totals};
+
+    fn totals(acquisitions: u64, blocked: u64, hold_micros: u64) -> Totals {
+        Totals {
+            acquisitions,
+            blocked,
+            hold_nanos: acquisitions * hold_micros * 1000,
+        }
+    }
+
+    #[test]
+    fn splits_when_blocking() {
+        let adaptive = Adaptive::default();
+        assert_eq!(adaptive.chunks_for(4, 1000, totals(100, 20, 50)), 8);
+        assert_eq!(adaptive.chunks_for(4, 1000, totals(100, 12, 50)), 4);
+        // Not beyond the number of elements.
+        assert_eq!(adaptive.chunks_for(4, 6, totals(100, 20, 50)), 6);
+        assert_eq!(adaptive.chunks_for(800, 10_000, totals(100, 20, 50)), 1024);
+    }
+
+    #[test]
+    fn merges_when_cheap() {
+        let adaptive = Adaptive::default();
+        assert_eq!(adaptive.chunks_for(8, 1000, totals(100, 0, 1)), 4);
+        assert_eq!(adaptive.chunks_for(8, 1000, totals(100, 0, 50)), 8);
+        assert_eq!(adaptive.chunks_for(8, 1000, totals(100, 1, 1)), 8);
+        assert_eq!(adaptive.chunks_for(1, 1000, totals(100, 0, 1)), 1);
+
+        let adaptive = Adaptive { min_chunks: 6, ..Adaptive::default() };
+        assert_eq!(adaptive.chunks_for(8, 1000, totals(100, 0, 1)), 6);
+    }
+}
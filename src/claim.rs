@@ -0,0 +1,347 @@
+//! Bitmaps of which chunks are locked, so that an iterator
+//! can find a free one without trying every chunk's lock.
+//!
+//! The cane keeps a `Board` with a bit per chunk, set while that
+//! chunk is locked, and above it a summary bit per word of those,
+//! set while every chunk in that word is locked. Each iterator
+//! keeps a `Wanted` of the same shape for the chunks it has yet
+//! to visit. A free chunk is then one atomic load of a word and a
+//! `trailing_zeros` away, and full words are skipped 64 at a time.
+//!
+//! The board is only a hint. Whoever gets a chunk's mutex owns the
+//! chunk, so a stale bit costs a failed `try_lock`, or an iterator
+//! falling back to waiting for a chunk, but never correctness.
+
+use arrayvec::ArrayVec;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const BITS: usize = u64::BITS as usize;
+
+/// How many words of bits a `Wanted` keeps without allocating,
+/// which covers as many chunks as adaptive canes go up to by
+/// default.
+const INLINE_WORDS: usize = 16;
+
+fn words_for(bits: usize) -> usize {
+    bits.div_ceil(BITS)
+}
+
+/// `word` with every bit below `bit` cleared.
+fn from_bit(word: u64, bit: usize) -> u64 {
+    word & (!0 << bit)
+}
+
+/// `word` with every bit above `bit` cleared.
+fn up_to_bit(word: u64, bit: usize) -> u64 {
+    word & (!0 >> (BITS - 1 - bit))
+}
+
+fn highest_bit(word: u64) -> usize {
+    BITS - 1 - word.leading_zeros() as usize
+}
+
+pub struct Board {
+    locked: Box<[AtomicU64]>,
+    full: Box<[AtomicU64]>,
+}
+
+impl Board {
+    pub fn new(chunks: usize) -> Self {
+        let locked = (0..words_for(chunks))
+            .map(|word| {
+                // Bits past the last chunk count as locked,
+                // so that the last word can be full too.
+                let used = (chunks - word * BITS).min(BITS);
+                AtomicU64::new(if used == BITS { 0 } else { !0 << used })
+            })
+            .collect();
+        let full = (0..words_for(words_for(chunks))).map(|_| AtomicU64::new(0)).collect();
+
+        Self { locked, full }
+    }
+
+    /// Must be called once `chunk`'s lock is taken.
+    pub fn locked(&self, chunk: usize) {
+        let (word, bit) = (chunk / BITS, 1 << (chunk % BITS));
+        if self.locked[word].fetch_or(bit, Ordering::SeqCst) | bit == !0 {
+            let summary = 1 << (word % BITS);
+            self.full[word / BITS].fetch_or(summary, Ordering::SeqCst);
+            // A chunk may have been unlocked before the summary was
+            // set, by someone who then had nothing to clear.
+            if self.locked[word].load(Ordering::SeqCst) != !0 {
+                self.full[word / BITS].fetch_and(!summary, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Must be called before `chunk`'s lock is released.
+    pub fn unlocked(&self, chunk: usize) {
+        let (word, bit) = (chunk / BITS, 1 << (chunk % BITS));
+        if self.locked[word].fetch_and(!bit, Ordering::SeqCst) == !0 {
+            self.full[word / BITS].fetch_and(!(1 << (word % BITS)), Ordering::SeqCst);
+        }
+    }
+}
+
+/// Words of bits, kept inline until there are too many of them.
+enum Bits {
+    Inline(ArrayVec<u64, INLINE_WORDS>),
+    Heap(Vec<u64>),
+}
+
+impl Default for Bits {
+    fn default() -> Self {
+        Bits::Inline(ArrayVec::new())
+    }
+}
+
+impl Bits {
+    /// `len` words, all zero. Once on the heap, stays there, so
+    /// that only growing past what it had before allocates.
+    fn zeroed(&mut self, len: usize) {
+        match self {
+            Bits::Inline(words) if len <= INLINE_WORDS => {
+                words.clear();
+                words.extend(std::iter::repeat_n(0, len));
+            }
+            Bits::Inline(_) => *self = Bits::Heap(vec![0; len]),
+            Bits::Heap(words) => {
+                words.clear();
+                words.resize(len, 0);
+            }
+        }
+    }
+}
+
+impl Deref for Bits {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        match self {
+            Bits::Inline(words) => words,
+            Bits::Heap(words) => words,
+        }
+    }
+}
+
+impl DerefMut for Bits {
+    fn deref_mut(&mut self) -> &mut [u64] {
+        match self {
+            Bits::Inline(words) => words,
+            Bits::Heap(words) => words,
+        }
+    }
+}
+
+/// The chunks an iterator has yet to visit.
+#[derive(Default)]
+pub struct Wanted {
+    words: Bits,
+    /// A bit per word of `words` which isn't zero.
+    summary: Bits,
+}
+
+impl Wanted {
+    #[cfg(test)]
+    pub(crate) fn new(chunks: std::ops::Range<usize>) -> Self {
+        let mut wanted = Self::default();
+        wanted.reset(chunks);
+        wanted
+    }
+
+    /// Wants exactly `chunks`. Only allocates if there are more
+    /// than `INLINE_WORDS` words of them, and more than this was
+    /// last reset to.
+    pub fn reset(&mut self, chunks: std::ops::Range<usize>) {
+        let (words, summary) = (&mut self.words, &mut self.summary);
+        words.zeroed(words_for(chunks.end));
+        summary.zeroed(words_for(words.len()));
+        for chunk in chunks {
+            words[chunk / BITS] |= 1 << (chunk % BITS);
+            summary[chunk / BITS / BITS] |= 1 << (chunk / BITS % BITS);
+        }
+    }
+
+    pub fn remove(&mut self, chunk: usize) {
+        let word = chunk / BITS;
+        self.words[word] &= !(1 << (chunk % BITS));
+        if self.words[word] == 0 {
+            self.summary[word / BITS] &= !(1 << (word % BITS));
+        }
+    }
+
+    /// The lowest chunk from `from` on which is wanted, and
+    /// which the board doesn't have as locked.
+    pub fn first_free(&self, board: &Board, from: usize) -> Option<usize> {
+        self.first(from, |word| board.locked[word].load(Ordering::Relaxed), |index| {
+            board.full[index].load(Ordering::Relaxed)
+        })
+    }
+
+    /// The highest chunk below `below` which is wanted, and
+    /// which the board doesn't have as locked.
+    pub fn last_free(&self, board: &Board, below: usize) -> Option<usize> {
+        self.last(below, |word| board.locked[word].load(Ordering::Relaxed), |index| {
+            board.full[index].load(Ordering::Relaxed)
+        })
+    }
+
+    /// The lowest wanted chunk from `from` on.
+    pub fn first_wanted(&self, from: usize) -> Option<usize> {
+        self.first(from, |_| 0, |_| 0)
+    }
+
+    /// `taken` and `full` give the chunks and words to skip.
+    fn first(&self, from: usize, taken: impl Fn(usize) -> u64, full: impl Fn(usize) -> u64) -> Option<usize> {
+        let mut word = from / BITS;
+        let mut bit = from % BITS;
+
+        while word < self.words.len() {
+            let index = word / BITS;
+            let words = from_bit(self.summary[index] & !full(index), word % BITS);
+            if words == 0 {
+                word = (index + 1) * BITS;
+                bit = 0;
+                continue;
+            }
+
+            let next = index * BITS + words.trailing_zeros() as usize;
+            if next != word {
+                bit = 0;
+            }
+
+            let chunks = from_bit(self.words[next] & !taken(next), bit);
+            if chunks != 0 {
+                return Some(next * BITS + chunks.trailing_zeros() as usize);
+            }
+            word = next + 1;
+            bit = 0;
+        }
+
+        None
+    }
+
+    /// Like `first`, but downwards.
+    fn last(&self, below: usize, taken: impl Fn(usize) -> u64, full: impl Fn(usize) -> u64) -> Option<usize> {
+        let mut end = below.min(self.words.len() * BITS);
+
+        while end > 0 {
+            let (word, bit) = ((end - 1) / BITS, (end - 1) % BITS);
+            let index = word / BITS;
+            let words = up_to_bit(self.summary[index] & !full(index), word % BITS);
+            if words == 0 {
+                end = index * BITS * BITS;
+                continue;
+            }
+
+            let prev = index * BITS + highest_bit(words);
+            let chunks = self.words[prev] & !taken(prev);
+            let chunks = if prev == word { up_to_bit(chunks, bit) } else { chunks };
+            if chunks != 0 {
+                return Some(prev * BITS + highest_bit(chunks));
+            }
+            end = prev * BITS;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Board, Wanted};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn finds_free_chunks() {
+        let board = Board::new(200);
+        let mut wanted = Wanted::new(3..150);
+        assert_eq!(wanted.first_free(&board, 0), Some(3));
+
+        board.locked(3);
+        board.locked(5);
+        assert_eq!(wanted.first_free(&board, 0), Some(4));
+        assert_eq!(wanted.first_free(&board, 5), Some(6));
+        wanted.remove(4);
+        assert_eq!(wanted.first_free(&board, 0), Some(6));
+        assert_eq!(wanted.first_wanted(0), Some(3));
+
+        board.unlocked(3);
+        assert_eq!(wanted.first_free(&board, 0), Some(3));
+        assert_eq!(wanted.first_free(&board, 150), None);
+    }
+
+    #[test]
+    fn skips_full_words() {
+        let board = Board::new(130);
+        let wanted = Wanted::new(0..130);
+        (0..128).for_each(|chunk| board.locked(chunk));
+        assert_eq!(board.full[0].load(Ordering::SeqCst), 0b11);
+        assert_eq!(wanted.first_free(&board, 0), Some(128));
+
+        board.locked(128);
+        board.locked(129);
+        // The bits past the last chunk count as locked.
+        assert_eq!(board.full[0].load(Ordering::SeqCst), 0b111);
+        assert_eq!(wanted.first_free(&board, 0), None);
+
+        board.unlocked(70);
+        assert_eq!(board.full[0].load(Ordering::SeqCst), 0b101);
+        assert_eq!(wanted.first_free(&board, 0), Some(70));
+    }
+
+    #[test]
+    fn finds_free_chunks_downwards() {
+        let board = Board::new(200);
+        let mut wanted = Wanted::new(3..150);
+        assert_eq!(wanted.last_free(&board, 200), Some(149));
+
+        board.locked(149);
+        wanted.remove(148);
+        assert_eq!(wanted.last_free(&board, 200), Some(147));
+        assert_eq!(wanted.last_free(&board, 64), Some(63));
+        assert_eq!(wanted.last_free(&board, 3), None);
+
+        (3..128).for_each(|chunk| board.locked(chunk));
+        assert_eq!(wanted.last_free(&board, 128), None);
+        assert_eq!(wanted.last_free(&board, 200), Some(147));
+    }
+
+    #[test]
+    fn reset_forgets_old_chunks() {
+        let board = Board::new(200);
+        let mut wanted = Wanted::new(0..200);
+        wanted.reset(70..72);
+        assert_eq!(wanted.first_free(&board, 0), Some(70));
+        assert_eq!(wanted.last_free(&board, 200), Some(71));
+        wanted.reset(0..0);
+        assert_eq!(wanted.first_wanted(0), None);
+    }
+
+    #[test]
+    fn spills_past_inline_words() {
+        let board = Board::new(2000);
+        let mut wanted = Wanted::new(0..10);
+        wanted.reset(1990..2000);
+        assert_eq!(wanted.first_free(&board, 0), Some(1990));
+        wanted.reset(3..5);
+        assert_eq!(wanted.first_free(&board, 0), Some(3));
+        assert_eq!(wanted.last_free(&board, 2000), Some(4));
+    }
+
+    #[test]
+    fn many_summary_words() {
+        let chunks = 64 * 64 * 2 + 5;
+        let board = Board::new(chunks);
+        let mut wanted = Wanted::new(10..chunks);
+        (10..chunks - 1).for_each(|chunk| wanted.remove(chunk));
+        assert_eq!(wanted.first_free(&board, 0), Some(chunks - 1));
+        board.locked(chunks - 1);
+        assert_eq!(wanted.first_free(&board, 0), None);
+        assert_eq!(wanted.first_wanted(0), Some(chunks - 1));
+        assert_eq!(wanted.last_free(&board, chunks), None);
+        board.unlocked(chunks - 1);
+        assert_eq!(wanted.last_free(&board, chunks), Some(chunks - 1));
+    }
+}
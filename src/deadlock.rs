@@ -0,0 +1,259 @@
+//! Keeps track of the cane locks each thread holds in debug
+//! builds, so that a thread asking for a lock which it is
+//! itself keeping from being granted panics instead of hanging.
+//!
+//! Canes are told apart by the address of their `all_lock`,
+//! which can't move while any of their locks are held. In
+//! release builds nothing is tracked and every check passes,
+//! except for the one optimistic readers make before they lock
+//! a chunk, see `check_chunk_holder`.
+//!
+//! A guard unlocked on a different thread than it was locked
+//! on (only possible for `GuardSend` locks) isn't tracked
+//! properly, and may cause spurious panics later on.
+
+use std::fmt;
+
+use std::cell::RefCell;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Held {
+    Shared,
+    Exclusive,
+    Upgradable,
+    Chunk(usize),
+    /// Part of a chunk stolen out of the slot at `slot`, which
+    /// its owner waits for before it lets go of the chunk.
+    Lent { chunk: usize, slot: usize },
+}
+
+impl fmt::Display for Held {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Held::Shared => f.write_str("a shared lock (from a streaming iterator)"),
+            Held::Exclusive => f.write_str("the exclusive lock (from a write guard)"),
+            Held::Upgradable => f.write_str("the upgradable lock (from an upgradable read)"),
+            Held::Chunk(chunk) => write!(f, "the lock on chunk {}", chunk),
+            Held::Lent { chunk, .. } => write!(f, "part of chunk {} (lent by another iterator)", chunk),
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    static HELD: RefCell<Vec<(usize, Held)>> = const { RefCell::new(Vec::new()) };
+}
+
+thread_local! {
+    /// The chunks this thread has parts of lent to it, as `(cane,
+    /// chunk)`, in every build. Stealing is rare enough for this
+    /// to cost next to nothing.
+    static LENT: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+    /// Only its address is used, to tell threads apart.
+    static TOKEN: u8 = const { 0 };
+}
+
+/// Tells this thread apart from every other one that's running.
+/// Never zero.
+#[inline]
+pub(crate) fn thread_token() -> usize {
+    TOKEN.with(|token| token as *const u8 as usize)
+}
+
+/// What the registry identifies a cane by.
+pub(crate) fn id_of<R>(all_lock: &R) -> usize {
+    all_lock as *const R as usize
+}
+
+#[inline]
+pub(crate) fn acquired(_cane: usize, _held: Held) {
+    #[cfg(debug_assertions)]
+    HELD.with(|held| held.borrow_mut().push((_cane, _held)));
+}
+
+#[inline]
+pub(crate) fn released(_cane: usize, _held: Held) {
+    // `try_with`, since guards may be dropped while the
+    // thread locals are being torn down.
+    #[cfg(debug_assertions)]
+    let _ = HELD.try_with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(index) = held.iter().rposition(|&x| x == (_cane, _held)) {
+            held.remove(index);
+        }
+    });
+}
+
+#[cfg(debug_assertions)]
+fn held_on(cane: usize) -> Vec<Held> {
+    HELD.with(|held| held.borrow().iter().filter(|x| x.0 == cane).map(|x| x.1).collect())
+}
+
+/// Like `held_on(cane).contains(&what)`, without allocating, for
+/// checks made whenever an iterator moves on.
+#[cfg(debug_assertions)]
+fn holds(cane: usize, what: Held) -> bool {
+    HELD.with(|held| held.borrow().contains(&(cane, what)))
+}
+
+/// Panics if `write()` could never get the exclusive lock,
+/// because this thread holds any other lock on the cane.
+#[inline]
+pub(crate) fn check_write(_cane: usize) {
+    #[cfg(debug_assertions)]
+    check_alone(_cane, None, "write()");
+}
+
+/// Panics if `upgradable_read()` could never get the upgradable
+/// lock, or couldn't be upgraded later, because this thread holds
+/// any other lock on the cane.
+#[inline]
+pub(crate) fn check_upgradable(_cane: usize) {
+    #[cfg(debug_assertions)]
+    check_alone(_cane, None, "upgradable_read()");
+}
+
+/// Panics if upgrading could never get the exclusive lock, because
+/// this thread holds a lock on the cane besides the upgradable one.
+#[inline]
+pub(crate) fn check_upgrade(_cane: usize) {
+    #[cfg(debug_assertions)]
+    check_alone(_cane, Some(Held::Upgradable), "upgrade()");
+}
+
+#[cfg(debug_assertions)]
+fn check_alone(cane: usize, except: Option<Held>, what: &str) {
+    let mut held = held_on(cane);
+    if let Some(except) = except {
+        if let Some(index) = held.iter().position(|&x| x == except) {
+            held.remove(index);
+        }
+    }
+    if !held.is_empty() {
+        let held = held.iter().map(Held::to_string).collect::<Vec<_>>();
+        panic!(
+            "self-deadlock: called `{}` on the candy cane at {:#x}, but this thread already holds {} on it",
+            what,
+            cane,
+            held.join(" and "),
+        );
+    }
+}
+
+/// Panics if this thread holds the exclusive lock on the cane,
+/// and otherwise returns which shared or upgradable one it
+/// already holds, if any.
+#[inline]
+pub(crate) fn check_read(_cane: usize) -> Option<Held> {
+    #[cfg(debug_assertions)]
+    {
+        let held = held_on(_cane);
+        if held.contains(&Held::Exclusive) {
+            panic!(
+                "self-deadlock: tried to take a shared lock on the candy cane at {:#x}, but this thread already holds {} on it",
+                _cane,
+                Held::Exclusive,
+            );
+        }
+
+        held.into_iter().find(|&x| x == Held::Shared || x == Held::Upgradable)
+    }
+
+    #[cfg(not(debug_assertions))]
+    None
+}
+
+/// Called when a thread which already holds a shared or upgradable
+/// lock finds a writer waiting, since that writer is waiting on us.
+#[cold]
+pub(crate) fn writer_waiting(cane: usize, held: Held) -> ! {
+    panic!(
+        "self-deadlock: tried to take another shared lock on the candy cane at {:#x} while a writer is waiting, \
+         but that writer is waiting for this thread to release {}",
+        cane,
+        held,
+    );
+}
+
+/// Panics if blocking on `chunk` could never return, because
+/// this thread is the one holding it.
+#[inline]
+pub(crate) fn check_chunk(_cane: usize, _chunk: usize) {
+    #[cfg(debug_assertions)]
+    if held_on(_cane).contains(&Held::Chunk(_chunk)) {
+        panic!(
+            "self-deadlock: waiting for {} of the candy cane at {:#x}, but this thread already holds it \
+             (is another iterator over the same elements alive on this thread?)",
+            Held::Chunk(_chunk),
+            _cane,
+        );
+    }
+}
+
+/// Must be called when this thread is lent part of `chunk`, and
+/// again with `lent` false when it gives it back.
+pub(crate) fn lent(cane: usize, chunk: usize, lent: bool) {
+    // `try_with`, as in `released`.
+    let _ = LENT.try_with(|parts| {
+        let mut parts = parts.borrow_mut();
+        if lent {
+            parts.push((cane, chunk));
+        } else if let Some(index) = parts.iter().rposition(|&x| x == (cane, chunk)) {
+            parts.swap_remove(index);
+        }
+    });
+}
+
+/// Panics if blocking on `chunk` could never return, because this
+/// thread holds it, or has part of it lent by whoever does, which
+/// they wait for before they let go of it. `holder` is the chunk's
+/// `holder`.
+///
+/// Unlike the other checks, this one's made in every build, since
+/// optimistic readers hang otherwise, and so is cheap enough to be.
+pub(crate) fn check_chunk_holder(cane: usize, chunk: usize, holder: usize) {
+    if holder == thread_token() {
+        panic!(
+            "self-deadlock: waiting for {} of the candy cane at {:#x}, but this thread already holds it \
+             (is an iterator or `write_range` guard over the same elements alive on this thread?)",
+            Held::Chunk(chunk),
+            cane,
+        );
+    }
+    if LENT.with(|parts| parts.borrow().contains(&(cane, chunk))) {
+        panic!(
+            "self-deadlock: waiting for {} of the candy cane at {:#x}, but this thread has part of it \
+             lent, which its holder is waiting for (is another iterator over the same elements alive on this thread?)",
+            Held::Chunk(chunk),
+            cane,
+        );
+    }
+}
+
+/// Whether this thread holds `chunk`. Always `false` in release
+/// builds, where nothing is tracked.
+#[inline]
+pub(crate) fn holds_chunk(_cane: usize, _chunk: usize) -> bool {
+    #[cfg(debug_assertions)]
+    {
+        holds(_cane, Held::Chunk(_chunk))
+    }
+
+    #[cfg(not(debug_assertions))]
+    false
+}
+
+/// Panics if waiting for what was stolen out of the slot at `slot`
+/// to be given back could never return, because this thread has it.
+#[inline]
+pub(crate) fn check_thieves(_cane: usize, _chunk: usize, _slot: usize) {
+    #[cfg(debug_assertions)]
+    if holds(_cane, Held::Lent { chunk: _chunk, slot: _slot }) {
+        panic!(
+            "self-deadlock: waiting for part of chunk {} of the candy cane at {:#x} to be given back, \
+             but this thread has it (is another iterator over the same elements alive on this thread?)",
+            _chunk,
+            _cane,
+        );
+    }
+}
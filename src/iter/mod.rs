@@ -1,12 +1,73 @@
-use parking_lot::lock_api::{RawRwLock, RawMutex};
-use crate::RawCandyCane;
-use crate::slice_tracker::SliceTracker;
-use std::sync::atomic::Ordering;
-use std::cell::UnsafeCell;
+use std::ops::{Bound, Range, RangeBounds};
+use std::fmt;
 
 // pub mod normal;
 pub mod streaming;
 
+/// Why a range could not be used to index into a
+/// candy cane.
+///
+/// These mirror the panics that indexing a `[T]`
+/// with an invalid range would produce.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RangeError {
+    /// `start > end`.
+    StartAfterEnd { start: usize, end: usize },
+    /// `end > len`.
+    EndOutOfBounds { end: usize, len: usize },
+    /// The start was `Bound::Excluded(usize::MAX)`.
+    StartOverflow,
+    /// The end was `Bound::Included(usize::MAX)`.
+    EndOverflow,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RangeError::StartAfterEnd { start, end } => {
+                write!(f, "range starts at {} but ends at {}", start, end)
+            }
+            RangeError::EndOutOfBounds { end, len } => {
+                write!(f, "range end index {} out of range for candy cane of length {}", end, len)
+            }
+            RangeError::StartOverflow => f.write_str("attempted to index candy cane from after maximum usize"),
+            RangeError::EndOverflow => f.write_str("attempted to index candy cane up to maximum usize"),
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Converts any `RangeBounds<usize>` into the half open
+/// range it would select from a `[T]` of length `len`.
+///
+/// This is the only place ranges handed to us by users
+/// get interpreted, so that every iterator agrees with
+/// slice indexing.
+pub(crate) fn normalize_range<R: RangeBounds<usize> + ?Sized>(range: &R, len: usize) -> Result<Range<usize>, RangeError> {
+    let start = match range.start_bound() {
+        Bound::Included(&s) => s,
+        Bound::Excluded(&s) => s.checked_add(1).ok_or(RangeError::StartOverflow)?,
+        Bound::Unbounded => 0,
+    };
+
+    let end = match range.end_bound() {
+        Bound::Included(&e) => e.checked_add(1).ok_or(RangeError::EndOverflow)?,
+        Bound::Excluded(&e) => e,
+        Bound::Unbounded => len,
+    };
+
+    if start > end {
+        return Err(RangeError::StartAfterEnd { start, end });
+    }
+
+    if end > len {
+        return Err(RangeError::EndOutOfBounds { end, len });
+    }
+
+    Ok(start..end)
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub(crate) enum ChunkVisitRange {
     All,
@@ -22,84 +83,106 @@ pub(crate) enum ChunkVisitRange {
     Inside(usize, usize),
 }
 
-unsafe fn unsafe_cell_to_ref<T>(x: &[UnsafeCell<T>]) -> &[T] {
-    std::slice::from_raw_parts(x.as_ptr().cast(), x.len())
-}
-
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub(crate) struct ChunkVisit {
-    chunk_id: usize,
-    range: ChunkVisitRange,
+    /// Index into the whole array of `SliceTracker`s.
+    pub(crate) chunk_id: usize,
+    pub(crate) range: ChunkVisitRange,
 }
 
 impl ChunkVisit {
-    pub fn create_range<'a, Lock: RawRwLock, M: RawMutex, T, const SLICES: usize>(
-        start: usize,
-        end: usize,
-        slice_buffer: &mut Vec<Self>,
-        buffer: &'a RawCandyCane<Lock, M, T, SLICES>,
-    ) -> &'a [SliceTracker<M, T>] {
-        let start_slice = buffer.calc_slice_index(start);
-        let mut end_slice = buffer.calc_slice_index(end);
-        assert!(end_slice >= start_slice);
-        assert!(end_slice <= SLICES);
-
-        let len_per_slice = buffer.len_per_slice.load(Ordering::Acquire);
-
-        // Adjust if we are dealing with the last (and longer) slice.
-        if end_slice == SLICES {
-            end_slice = end_slice - 1;
+    /// Visits the part of `range` that lies in chunk `chunk_id`.
+    /// Edits to earlier chunks may have moved it out of `range`
+    /// since it was picked, in which case that's nothing.
+    pub(crate) fn within(chunk_id: usize, range: &Range<usize>, chunk_start: usize, chunk_len: usize) -> Self {
+        let chunk_end = chunk_start + chunk_len;
+
+        let local_start = range.start.clamp(chunk_start, chunk_end) - chunk_start;
+        let local_end = range.end.clamp(chunk_start, chunk_end).max(chunk_start + local_start) - chunk_start;
+
+        ChunkVisit::from_local(chunk_id, local_start..local_end, chunk_len)
+    }
+
+    /// Visits `range` of a chunk `chunk_len` long.
+    pub(crate) fn from_local(chunk_id: usize, range: Range<usize>, chunk_len: usize) -> Self {
+        let range = match (range.start == 0, range.end == chunk_len) {
+            (true, true) => ChunkVisitRange::All,
+            (true, false) => ChunkVisitRange::First { end: range.end },
+            (false, true) => ChunkVisitRange::Last { start: range.start },
+            (false, false) => ChunkVisitRange::Inside(range.start, range.end),
+        };
+
+        ChunkVisit { chunk_id, range }
+    }
+
+    /// The part of a chunk `chunk_len` long this visits.
+    pub(crate) fn local_range(&self, chunk_len: usize) -> Range<usize> {
+        match self.range {
+            ChunkVisitRange::All => 0..chunk_len,
+            ChunkVisitRange::Inside(s, e) => s..e,
+            ChunkVisitRange::First { end } => 0..end,
+            ChunkVisitRange::Last { start } => start..chunk_len,
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_range, RangeError};
+    use std::fmt::Debug;
+    use std::ops::{Bound, RangeBounds};
+    use std::slice::SliceIndex;
 
-        if start_slice == end_slice {
-            let slices = &buffer.slices[start_slice..=start_slice];
-            let slice_offset = start_slice * len_per_slice;
-            let start = start - slice_offset;
-            let end = end - slice_offset;
-            slice_buffer.push(ChunkVisit {
-                chunk_id: 0,
-                range: ChunkVisitRange::Inside(start, end),
-            });
-            return unsafe { unsafe_cell_to_ref(slices) };
+    fn check<R: RangeBounds<usize> + SliceIndex<[usize], Output = [usize]> + Clone + Debug>(range: R, len: usize) {
+        let data = (0..len).collect::<Vec<_>>();
+
+        match (data.get(range.clone()), normalize_range(&range, len)) {
+            (None, Err(_)) => {}
+            (Some(expected), Ok(found)) => assert_eq!(expected, &data[found]),
+            (expected, found) => panic!("{:?} over {}: expected {:?}, found {:?}", range, len, expected, found),
         }
+    }
+
+    #[test]
+    fn matches_slice_indexing() {
+        for len in 0..6 {
+            for s in 0..8 {
+                check(s.., len);
+                check((Bound::Excluded(s), Bound::Unbounded), len);
 
-        let slices = &buffer.slices[start_slice..=end_slice];
-
-        for (buffer_index, slice_index) in (start_slice..=end_slice).enumerate() {
-            if slice_index == start_slice {
-                let start_slice_offset = start_slice * len_per_slice;
-                let start = start - start_slice_offset;
-                slice_buffer.push(ChunkVisit {
-                    chunk_id: buffer_index,
-                    range: ChunkVisitRange::Last { start },
-                });
-            } else if slice_index == end_slice {
-                let end_slice_offset = end_slice * len_per_slice;
-                let end = end - end_slice_offset;
-                slice_buffer.push(ChunkVisit {
-                    chunk_id: buffer_index,
-                    range: ChunkVisitRange::First { end },
-                });
-            } else {
-                slice_buffer.push(ChunkVisit {
-                    chunk_id: buffer_index,
-                    range: ChunkVisitRange::All,
-                })
+                for e in 0..8 {
+                    check(s..e, len);
+                    check(s..=e, len);
+                    check((Bound::Excluded(s), Bound::Excluded(e)), len);
+                    check((Bound::Excluded(s), Bound::Included(e)), len);
+                }
+            }
+
+            for e in 0..8 {
+                check(..e, len);
+                check(..=e, len);
             }
-        }
 
-        unsafe {
-            // SAFETY: `UnsafeCell` is `repr(transparent)`.
-            unsafe_cell_to_ref(slices)
+            check(.., len);
         }
     }
 
-    fn slice<'a, T>(&self, slice: &'a [T]) -> &'a [T] {
-        match self.range {
-            ChunkVisitRange::All => slice,
-            ChunkVisitRange::Inside(s, e) => &slice[s..=e],
-            ChunkVisitRange::First { end } => &slice[..=end],
-            ChunkVisitRange::Last { start } => &slice[start..],
-        }
+    #[test]
+    fn errors() {
+        assert_eq!(normalize_range(&(Bound::Included(3), Bound::Excluded(2)), 5), Err(RangeError::StartAfterEnd { start: 3, end: 2 }));
+        assert_eq!(normalize_range(&(0..6), 5), Err(RangeError::EndOutOfBounds { end: 6, len: 5 }));
+        assert_eq!(normalize_range(&(..=0), 0), Err(RangeError::EndOutOfBounds { end: 1, len: 0 }));
+        assert_eq!(normalize_range(&(..=usize::MAX), 5), Err(RangeError::EndOverflow));
+        assert_eq!(
+            normalize_range(&(Bound::Excluded(usize::MAX), Bound::Unbounded), 5),
+            Err(RangeError::StartOverflow),
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(normalize_range(&(..), 0), Ok(0..0));
+        assert_eq!(normalize_range(&(2..2), 5), Ok(2..2));
+        assert_eq!(normalize_range(&(5..), 5), Ok(5..5));
     }
 }
@@ -1,127 +1,885 @@
-use crate::slice_tracker::{LockGuard, SliceTracker};
+use crate::claim::{Board, Wanted};
+use crate::deadlock::{self, Held};
+use crate::policy::{ChunkPolicy, Scan};
+use crate::slice_tracker::{ChunkGuard, LockGuard, SliceTracker};
+use crate::steal::{self, StealSlot};
+use crate::wait::{Backoff, Released, WaitStrategy};
+use crate::storage::Storage;
 use crate::RawCandyCane;
-use parking_lot::lock_api::{RawRwLock, MutexGuard, RawMutex};
+use arrayvec::ArrayVec;
+use parking_lot::lock_api::{RawRwLock, RawMutex};
 use parking_lot::RawRwLock as RwLock;
 use std::cell::UnsafeCell;
-use std::ops::{Bound, RangeBounds};
-use super::ChunkVisit;
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::{normalize_range, ChunkVisit, RangeError};
+
+/// How many leftover parts of chunks an iterator can keep
+/// track of. Once it has this many, it stops stealing.
+const MAX_LEFTOVERS: usize = 16;
+
+/// What an iterator keeps track of while it runs. Iterators
+/// which aren't handed one keep their own. Neither allocates,
+/// unless the cane has more than a thousand or so chunks. One
+/// that's kept and handed to [`iter_streaming_with`] again then
+/// only allocates when there are more chunks than last time.
+///
+/// [`iter_streaming_with`]: crate::RawCandyCane::iter_streaming_with
+#[derive(Default)]
+pub struct IterState {
+    /// The chunks none of which has been visited yet.
+    wanted: Wanted,
+    /// What's left of chunks which were stolen from. Each
+    /// counts towards its chunk's `revisits`.
+    leftovers: ArrayVec<ChunkVisit, MAX_LEFTOVERS>,
+    /// The ranges to go through once this one's done, last first.
+    /// Only interruptible iterators whose plan had to be made
+    /// again for new chunks have any.
+    plan: Vec<Range<usize>>,
+}
+
+impl IterState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for IterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IterState").finish_non_exhaustive()
+    }
+}
+
+// Not boxed, since then starting an iterator would allocate.
+#[allow(clippy::large_enum_variant)]
+enum State<'a> {
+    Owned(IterState),
+    Borrowed(&'a mut IterState),
+}
+
+impl Deref for State<'_> {
+    type Target = IterState;
+
+    fn deref(&self) -> &IterState {
+        match self {
+            State::Owned(state) => state,
+            State::Borrowed(state) => state,
+        }
+    }
+}
+
+impl DerefMut for State<'_> {
+    fn deref_mut(&mut self) -> &mut IterState {
+        match self {
+            State::Owned(state) => state,
+            State::Borrowed(state) => state,
+        }
+    }
+}
+
+/// What an [interruptible] iterator does when it finds that a
+/// writer rebuilt the cane's chunks while it had let go of the
+/// shared lock.
+///
+/// [interruptible]: crate::RawCandyCane::iter_streaming_interruptible
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OnLayoutChange {
+    /// Carries on with the positions it hadn't got to yet, in the
+    /// new chunks. Whatever the writer moved to or from there may
+    /// be missed, or visited again.
+    Replan,
+    /// Ends early, as if there was nothing left.
+    Stop,
+}
+
+/// Everything an iterator looks at of its cane, which may all be
+/// replaced while the shared lock isn't held.
+struct View<'a, M: RawMutex, T> {
+    slices: &'a [SliceTracker<M, T>],
+    board: &'a Board,
+    policy: &'a dyn ChunkPolicy,
+    wait: WaitStrategy,
+    generation: usize,
+    len: usize,
+    appended: usize,
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// SAFETY: Only valid for as long as a lock which keeps
+    /// writers out is held.
+    unsafe fn view(&self) -> View<'_, M, T> {
+        View {
+            slices: &*self.slices.get(),
+            board: &*self.board.get(),
+            policy: &**self.policy.get(),
+            wait: *self.wait.get(),
+            generation: self.generation.load(Ordering::Relaxed),
+            len: self.len.load(Ordering::Acquire),
+            appended: self.appended.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Lets an interruptible iterator take the shared lock again,
+/// without knowing how its cane stores the elements.
+trait Relock<R: RawRwLock, M: RawMutex, T> {
+    fn writer_waiting(&self) -> bool;
+
+    /// Waits for any writer to be done, like starting an iterator.
+    fn relock(&self) -> (LockGuard<'_, R>, View<'_, M, T>);
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Relock<R, M, T> for RawCandyCane<R, M, T, SLICES, S> {
+    fn writer_waiting(&self) -> bool {
+        *self.is_waiting_mut.lock()
+    }
+
+    fn relock(&self) -> (LockGuard<'_, R>, View<'_, M, T>) {
+        let guard = self.lock_internal_for_read();
+        // SAFETY: Handed back along with `guard`.
+        (guard, unsafe { self.view() })
+    }
+}
+
+/// Lets an iterator have an adaptive cane re-partitioned once
+/// it's done, without knowing how its cane stores the elements.
+trait Rebalance {
+    /// The shared lock must be held.
+    fn request_rebalance(&self);
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Rebalance for RawCandyCane<R, M, T, SLICES, S> {
+    fn request_rebalance(&self) {
+        RawCandyCane::request_rebalance(self)
+    }
+}
+
+/// The chunks which `range`, which isn't empty, lies in.
+fn chunks_over<M: RawMutex, T>(slices: &[SliceTracker<M, T>], range: &Range<usize>) -> Range<usize> {
+    // Empty chunks share their start with the next one, so
+    // this is the last chunk starting at or before `index`.
+    let chunk_of = |index| slices.partition_point(|x| x.start() <= index) - 1;
+    chunk_of(range.start)..chunk_of(range.end - 1) + 1
+}
+
+/// Where to start looking for free chunks among `chunks`.
+fn scan_over(policy: &dyn ChunkPolicy, chunks: Range<usize>) -> Scan {
+    match policy.scan(chunks.clone()) {
+        Scan::Up(chunk) if !chunks.contains(&chunk) => Scan::Up(chunks.start),
+        scan => scan,
+    }
+}
+
+/// What a slot is told apart by in the self-deadlock checks.
+fn slot_id(slot: &StealSlot) -> usize {
+    slot as *const StealSlot as usize
+}
+
+/// How we came to be iterating part of a chunk.
+enum Claim<'a, M: RawMutex> {
+    /// We hold the chunk's lock, and have it marked
+    /// as locked on the board.
+    Locked(#[allow(dead_code)] ChunkGuard<'a, M>, &'a Board),
+    /// Lent to us out of someone else's slot, which must be
+    /// told once we're done with it.
+    Lent(&'a StealSlot),
+}
+
+/// The part of a chunk currently being iterated.
+struct Current<'a, M: RawMutex, T> {
+    tracker: &'a SliceTracker<M, T>,
+    /// Which cane and chunk, for the self-deadlock checks.
+    cane: usize,
+    chunk: usize,
+    /// Where what's left is published for thieves, if
+    /// there was a free slot for it.
+    slot: Option<&'a StealSlot>,
+    /// What's left, when there was no slot.
+    rest: Range<usize>,
+    /// The end of everything we're responsible for,
+    /// including parts which were lent out.
+    end: usize,
+    claim: Claim<'a, M>,
+    /// How to wait for thieves, and what wakes us.
+    wait: WaitStrategy,
+    released: &'a Released,
+}
+
+impl<'a, M: RawMutex, T> Current<'a, M, T> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        tracker: &'a SliceTracker<M, T>,
+        cane: usize,
+        chunk: usize,
+        range: Range<usize>,
+        slot: Option<&'a StealSlot>,
+        claim: Claim<'a, M>,
+        wait: WaitStrategy,
+        released: &'a Released,
+    ) -> Self {
+        let end = range.end;
+        let rest = match slot {
+            Some(slot) => {
+                slot.publish(range.clone());
+                range.end..range.end
+            }
+            None => range,
+        };
+        if let Claim::Lent(from) = claim {
+            deadlock::acquired(cane, Held::Lent { chunk, slot: slot_id(from) });
+            deadlock::lent(cane, chunk, true);
+        }
+
+        Self {
+            tracker,
+            cane,
+            chunk,
+            slot,
+            rest,
+            end,
+            claim,
+            wait,
+            released,
+        }
+    }
+
+    /// `range` must have come from `next_batch`.
+    fn slice(&self, range: Range<usize>) -> &'a [UnsafeCell<T>] {
+        // SAFETY: `range` is in bounds, and was claimed by us alone.
+        unsafe { std::slice::from_raw_parts(self.tracker.data().add(range.start), range.len()) }
+    }
+
+    fn next_batch(&mut self) -> Option<Range<usize>> {
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None if self.rest.is_empty() => return None,
+            None => return Some(std::mem::replace(&mut self.rest, self.end..self.end)),
+        };
+
+        loop {
+            if let Some(range) = slot.claim() {
+                return Some(range);
+            }
+
+            // Whatever was stolen from us has to be visited by us too,
+            // once it's been given back.
+            deadlock::check_thieves(self.cane, self.chunk, slot_id(slot));
+            let end = slot.wait_for_thieves(self.wait, self.released);
+            if end == self.end {
+                return None;
+            }
+            slot.publish(end..self.end);
+        }
+    }
+}
+
+impl<'a, M: RawMutex, T> Drop for Current<'a, M, T> {
+    fn drop(&mut self) {
+        // If we stopped early, nothing else may be taken from
+        // our slot, and thieves must be done before we let go.
+        if let Some(slot) = self.slot {
+            while slot.claim().is_some() {}
+            deadlock::check_thieves(self.cane, self.chunk, slot_id(slot));
+            slot.wait_for_thieves(self.wait, self.released);
+        }
+
+        match self.claim {
+            Claim::Locked(_, board) => board.unlocked(self.chunk),
+            Claim::Lent(from) => {
+                if let Some(slot) = self.slot {
+                    slot.release();
+                }
+                deadlock::released(self.cane, Held::Lent { chunk: self.chunk, slot: slot_id(from) });
+                deadlock::lent(self.cane, self.chunk, false);
+                from.give_back(self.released);
+            }
+        }
+    }
+}
 
 pub struct RawCandyCaneIterStreaming<'a, R: RawRwLock, M: RawMutex, T> {
+    /// What's left of the batch `internal` last handed us. All
+    /// that happens per element is taking the next one of these.
+    batch: std::slice::Iter<'a, UnsafeCell<T>>,
     slices: &'a [SliceTracker<M, T>],
+    board: &'a Board,
+    /// `None` when running under a lock that isn't ours, which
+    /// is held for at least as long as we are.
     #[allow(dead_code)]
-    all_lock: LockGuard<'a, R>,
-    pub(crate) chunks_to_visit: Vec<ChunkVisit>,
-    internal: Option<(std::slice::Iter<'a, UnsafeCell<T>>, MutexGuard<'a, M, ()>)>,
+    all_lock: Option<LockGuard<'a, R>>,
+    cane: usize,
+    range: Range<usize>,
+    /// How many appended elements the cane's last chunk counts,
+    /// and how many there were when we started, since we leave
+    /// out anything appended after that.
+    settled: &'a AtomicUsize,
+    appended_before: usize,
+    state: State<'a>,
+    policy: &'a dyn ChunkPolicy,
+    scan: Scan,
+    wait: WaitStrategy,
+    released: &'a Released,
+    internal: Option<Current<'a, M, T>>,
+    /// Set for interruptible iterators, which let go of the shared
+    /// lock between chunks whenever a writer is waiting for it.
+    relock: Option<(&'a dyn Relock<R, M, T>, OnLayoutChange)>,
+    /// Set when the cane is adaptive, and we took the shared
+    /// lock ourselves.
+    rebalance: Option<&'a dyn Rebalance>,
+    /// The cane's generation when we last took the shared lock.
+    generation: usize,
+    layout_changed: bool,
+    /// Whether what we hand out may be written to, which optimistic
+    /// readers have to be told about.
+    writes: bool,
 }
 
 impl<'a, Rw: RawRwLock, Mtx: RawMutex, T> RawCandyCaneIterStreaming<'a, Rw, Mtx, T> {
-    pub fn new_over<R: RangeBounds<usize>, const SLICES: usize>(
+    pub fn new_over<R: RangeBounds<usize>, const SLICES: usize, S: Storage>(
         range: R,
-        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES>,
-    ) -> Self {
-        let guard = buffer.lock_internal_for_read();
-
-        let mut chunk_buffer = Vec::new();
-
-        let start = range.start_bound();
-        let end = range.end_bound();
-        let len = buffer.len() - 1;
-
-        let (start, end) = match (start, end) {
-            (Bound::Excluded(&s), Bound::Excluded(&e)) => (s + 1, e + 1),
-            (Bound::Excluded(&s), Bound::Included(&e)) => (s + 1, e),
-            (Bound::Excluded(&s), Bound::Unbounded) => (s + 1, len),
-            (Bound::Included(&s), Bound::Excluded(&e)) => (s, e + 1),
-            (Bound::Included(&s), Bound::Included(&e)) => (s, e),
-            (Bound::Included(&s), Bound::Unbounded) => (s, len),
-            (Bound::Unbounded, Bound::Excluded(&e)) => (0, e + 1),
-            (Bound::Unbounded, Bound::Included(&e)) => (0, e),
-            (Bound::Unbounded, Bound::Unbounded) => (0, len),
-        };
+        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES, S>,
+    ) -> Result<Self, RangeError> {
+        Self::new_in(range, buffer, Some(buffer.lock_internal_for_read()), State::Owned(IterState::default()), None)
+    }
+
+    /// Like `new_over`, but keeps track of where it's
+    /// got to in `state`, so that that can be reused.
+    pub fn new_over_with<R: RangeBounds<usize>, const SLICES: usize, S: Storage>(
+        range: R,
+        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES, S>,
+        state: &'a mut IterState,
+    ) -> Result<Self, RangeError> {
+        Self::new_in(range, buffer, Some(buffer.lock_internal_for_read()), State::Borrowed(state), None)
+    }
 
-        assert!(start <= end);
-        assert!(end <= len);
+    /// Like `new_over`, but lets go of the shared lock between
+    /// chunks whenever a writer is waiting for it.
+    pub fn new_interruptible<R: RangeBounds<usize>, const SLICES: usize, S: Storage>(
+        range: R,
+        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES, S>,
+        on_change: OnLayoutChange,
+    ) -> Result<Self, RangeError> {
+        Self::new_in(range, buffer, Some(buffer.lock_internal_for_read()), State::Owned(IterState::default()), Some(on_change))
+    }
 
-        let slices = if start == end {
-            &[][..]
-        } else {
-            ChunkVisit::create_range(start, end, &mut chunk_buffer, &buffer)
+    /// Like `new_over`, but takes no lock of its own.
+    ///
+    /// SAFETY: The caller must hold a lock on `buffer` which keeps
+    /// writers out, such as the upgradable one, for all of `'a`.
+    pub(crate) unsafe fn new_under<R: RangeBounds<usize>, const SLICES: usize, S: Storage>(
+        range: R,
+        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES, S>,
+        state: Option<&'a mut IterState>,
+    ) -> Result<Self, RangeError> {
+        let state = match state {
+            Some(state) => State::Borrowed(state),
+            None => State::Owned(IterState::default()),
         };
+        Self::new_in(range, buffer, None, state, None)
+    }
 
-        Self {
-            slices,
+    fn new_in<R: RangeBounds<usize>, const SLICES: usize, S: Storage>(
+        range: R,
+        buffer: &'a RawCandyCane<Rw, Mtx, T, SLICES, S>,
+        guard: Option<LockGuard<'a, Rw>>,
+        mut state: State<'a>,
+        interruptible: Option<OnLayoutChange>,
+    ) -> Result<Self, RangeError> {
+        // SAFETY: `guard`, or the caller's lock, is held, and the
+        // trackers, board, policy and wait strategy are only
+        // changed under the exclusive lock.
+        let view = unsafe { buffer.view() };
+        // We can't use `buffer.len()`, since that
+        // would lock a second time.
+        let len = view.len;
+        let range = normalize_range(&range, len)?;
+
+        let chunks = if range.is_empty() { 0..0 } else { chunks_over(view.slices, &range) };
+        // Elements inserted into or removed from a `Segmented` cane
+        // move everything after them, so a range up to the end
+        // keeps going up to the end.
+        let range = if range.end == len { range.start..usize::MAX } else { range };
+
+        let scan = scan_over(view.policy, chunks.clone());
+        let adaptive = guard.is_some() && view.slices[0].window.is_some();
+        state.wanted.reset(chunks);
+        state.leftovers.clear();
+        state.plan.clear();
+
+        Ok(Self {
+            batch: [].iter(),
+            slices: view.slices,
+            board: view.board,
             all_lock: guard,
-            chunks_to_visit: chunk_buffer,
+            cane: deadlock::id_of(&buffer.all_lock),
+            range,
+            settled: &buffer.settled,
+            appended_before: view.appended,
+            state,
+            policy: view.policy,
+            scan,
+            wait: view.wait,
+            released: &buffer.released,
             internal: None,
-        }
+            relock: interruptible.map(|on_change| (buffer as &dyn Relock<Rw, Mtx, T>, on_change)),
+            rebalance: adaptive.then_some(buffer as &dyn Rebalance),
+            generation: view.generation,
+            layout_changed: false,
+            writes: true,
+        })
+    }
+
+    /// For iterators which only ever hand out shared references.
+    pub(crate) fn read_only(mut self) -> Self {
+        self.writes = false;
+        self
+    }
+
+    /// Whether a writer rebuilt the cane's chunks while we had let
+    /// go of the shared lock.
+    pub fn layout_changed(&self) -> bool {
+        self.layout_changed
     }
 
+    #[inline]
     pub fn next_raw(&mut self) -> Option<*mut T> {
-        // println!("Running next");
-        match self.internal.as_mut().and_then(|(iter, _)| iter.next()) {
+        match self.batch.next() {
             Some(x) => Some(x.get()),
-            None => {
-                drop(self.internal.take());
-                // First, we try looking for a free chunk to access.
-                for index in (0..self.chunks_to_visit.len()).rev() {
-                    let chunk = &self.slices[self.chunks_to_visit[index].chunk_id];
-                    // println!("{:?} trying {} @ {}", std::thread::current().id(), index, self.chunks_to_visit[index].chunk_id);
-                    if let Some(guard) = chunk.try_lock() {
-                        // println!("{:?} try_lock-ed on {} @ {}", std::thread::current().id(), index, self.chunks_to_visit[index].chunk_id);
-                        let slice = unsafe {
-                            let slice =
-                                std::slice::from_raw_parts(chunk.data.as_ptr(), chunk.length);
-                            self.chunks_to_visit[index].slice(slice)
-                        };
-                        let mut iter = slice.iter();
-                        self.chunks_to_visit.remove(index);
-                        let item = match iter.next() {
-                            Some(x) => x,
-                            None => continue,
-                        };
-
-                        // println!("{:?} using {}", std::thread::current().id(), index);
-
-                        self.internal = Some((iter, guard));
-                        return Some(item.get());
-                    }
+            None => self.next_raw_cold(),
+        }
+    }
+
+    /// Everything left of the current batch at once, which
+    /// unlike single elements can be vectorized over.
+    #[inline]
+    pub fn next_slice_raw(&mut self) -> Option<*mut [T]> {
+        if self.batch.len() == 0 {
+            self.refill()?;
+        }
+
+        let batch = std::mem::replace(&mut self.batch, [].iter()).as_slice();
+        // `UnsafeCell<T>` is `repr(transparent)`.
+        Some(std::ptr::slice_from_raw_parts_mut(UnsafeCell::raw_get(batch.as_ptr()), batch.len()))
+    }
+
+    #[cold]
+    fn next_raw_cold(&mut self) -> Option<*mut T> {
+        self.refill()?;
+        self.batch.next().map(UnsafeCell::get)
+    }
+
+    /// Moves on to the next batch, from the part of a chunk
+    /// we're on or the next one. `None` once we're done.
+    fn refill(&mut self) -> Option<()> {
+        loop {
+            if self.refill_range().is_some() {
+                return Some(());
+            }
+            let next = self.state.plan.pop()?;
+            self.start_range(next);
+        }
+    }
+
+    /// Like `refill`, within the range we're on.
+    fn refill_range(&mut self) -> Option<()> {
+        if let Some(current) = self.internal.as_mut() {
+            if let Some(range) = current.next_batch() {
+                self.batch = current.slice(range).iter();
+                return Some(());
+            }
+        }
+        // Gives back anything lent to us.
+        drop(self.internal.take());
+        self.interrupt()?;
+
+        let cane = self.cane;
+        match self.wait {
+            WaitStrategy::Block => {
+                if let Some(x) = self.claim(cane) {
+                    return Some(x);
                 }
 
                 // If all of them are occupied, we simply wait on
                 // the next available one.
-                while let Some(chunk) = self.chunks_to_visit.pop() {
-                    // println!("{:?} locking on {}", std::thread::current().id(), chunk.chunk_id);
+                loop {
+                    let started = if let Some(visit) = self.state.leftovers.pop() {
+                        let guard = self.slices[visit.chunk_id].lock(cane, visit.chunk_id, self.released);
+                        self.start_leftover(visit, guard)
+                    } else {
+                        let chunk = self.state.wanted.first_wanted(0)?;
+                        self.state.wanted.remove(chunk);
+                        let guard = self.slices[chunk].lock(cane, chunk, self.released);
+                        self.start_wanted(chunk, guard)
+                    };
+                    if let Some(x) = started {
+                        return Some(x);
+                    }
+                }
+            }
+            WaitStrategy::Spin => {
+                let mut backoff = Backoff::default();
+                loop {
+                    if let Some(x) = self.claim(cane) {
+                        return Some(x);
+                    }
+                    self.check_waiting(cane)?;
+                    self.interrupt()?;
+                    backoff.snooze();
+                }
+            }
+            WaitStrategy::Park => loop {
+                let seen = self.released.generation();
+                if let Some(x) = self.claim(cane) {
+                    return Some(x);
+                }
+                self.check_waiting(cane)?;
+                self.interrupt()?;
+                self.released.park(seen);
+            },
+        }
+    }
 
-                    let tracker = &self.slices[chunk.chunk_id];
-                    let guard = tracker.lock();
+    /// Lets go of the shared lock if we're interruptible and a
+    /// writer is waiting for it, unless we have parts of chunks
+    /// to come back to, which edits wait for. `None` if the writer
+    /// rebuilt the chunks, and what's left of the range we're on
+    /// was either added to the plan again or dropped.
+    fn interrupt(&mut self) -> Option<()> {
+        let (relock, on_change) = match self.relock {
+            Some(relock) => relock,
+            None => return Some(()),
+        };
+        if !self.state.leftovers.is_empty() || !relock.writer_waiting() {
+            return Some(());
+        }
 
-                    let slice = unsafe {
-                        let slice =
-                            std::slice::from_raw_parts(tracker.data.as_ptr(), tracker.length);
-                        chunk.slice(slice)
-                    };
-                    let mut iter = slice.iter();
-                    let item = match iter.next() {
-                        Some(x) => x,
-                        None => continue,
+        // Where we are has to be worked out while the chunks are
+        // still the ones we know.
+        let planned = self.state.plan.len();
+        if on_change == OnLayoutChange::Replan {
+            self.plan_rest();
+        }
+        self.all_lock = None;
+        let (guard, view) = relock.relock();
+        self.all_lock = Some(guard);
+
+        self.slices = view.slices;
+        self.board = view.board;
+        self.policy = view.policy;
+        self.wait = view.wait;
+        if view.generation == self.generation {
+            // Every chunk is still where it was.
+            self.state.plan.truncate(planned);
+            return Some(());
+        }
+
+        self.generation = view.generation;
+        self.layout_changed = true;
+        // Rebuilding counts everything appended in the chunks'
+        // lengths, so only those from now on are left out.
+        self.appended_before = view.appended;
+        self.state.wanted.reset(0..0);
+        if on_change == OnLayoutChange::Stop {
+            self.state.plan.clear();
+        }
+        None
+    }
+
+    /// Adds what's left of the range we're on to the plan, as the
+    /// positions in the cane it's at now.
+    fn plan_rest(&mut self) {
+        let first = self.state.plan.len();
+        let mut from = 0;
+        while let Some(chunk) = self.state.wanted.first_wanted(from) {
+            from = chunk + 1;
+            let start = self.slices[chunk].start();
+            let rest = start.max(self.range.start)..(start + self.chunk_len(chunk)).min(self.range.end);
+            if rest.start >= rest.end {
+                continue;
+            }
+            match self.state.plan[first..].last_mut() {
+                Some(last) if last.end == rest.start => last.end = rest.end,
+                _ => self.state.plan.push(rest),
+            }
+        }
+        // The plan is taken from the back.
+        self.state.plan[first..].reverse();
+    }
+
+    /// Moves on to `range`, from the plan. Anything in it past the
+    /// end of the cane is gone.
+    fn start_range(&mut self, range: Range<usize>) {
+        let last = self.slices.len() - 1;
+        let range = range.start..range.end.min(self.slices[last].start() + self.chunk_len(last));
+        let chunks = if range.start < range.end { chunks_over(self.slices, &range) } else { 0..0 };
+
+        self.scan = scan_over(self.policy, chunks.clone());
+        self.state.wanted.reset(chunks);
+        self.range = range;
+    }
+
+    /// Starts on a free chunk, or part of a busy one, if any.
+    fn claim(&mut self, cane: usize) -> Option<()> {
+        // First, we try looking for a free chunk to access.
+        for index in (0..self.state.leftovers.len()).rev() {
+            let visit = self.state.leftovers[index];
+            if let Some(guard) = self.slices[visit.chunk_id].try_lock(cane, visit.chunk_id, self.released) {
+                self.state.leftovers.swap_remove(index);
+                if let Some(x) = self.start_leftover(visit, guard) {
+                    return Some(x);
+                }
+            }
+        }
+
+        while let Some((chunk, guard)) = self.take_free(cane) {
+            if let Some(x) = self.start_wanted(chunk, guard) {
+                return Some(x);
+            }
+        }
+
+        // Then for part of one that's busy.
+        self.steal()
+    }
+
+    /// Returns `None` if there's nothing left to wait for. In debug
+    /// builds, panics if this thread holds any chunk we still want,
+    /// since then no amount of waiting would get us it.
+    fn check_waiting(&self, cane: usize) -> Option<()> {
+        if self.state.leftovers.is_empty() && self.state.wanted.first_wanted(0).is_none() {
+            return None;
+        }
+
+        if cfg!(debug_assertions) {
+            self.state.leftovers.iter().for_each(|x| deadlock::check_chunk(cane, x.chunk_id));
+            let mut from = 0;
+            while let Some(chunk) = self.state.wanted.first_wanted(from) {
+                deadlock::check_chunk(cane, chunk);
+                from = chunk + 1;
+            }
+        }
+
+        Some(())
+    }
+
+    /// Locks the next free chunk we want, in the order the
+    /// policy asked for.
+    fn take_free(&mut self, cane: usize) -> Option<(usize, ChunkGuard<'a, Mtx>)> {
+        let (slices, released) = (self.slices, self.released);
+        let try_lock = |wanted: &mut Wanted, chunk: usize| {
+            let guard = slices[chunk].try_lock(cane, chunk, released)?;
+            wanted.remove(chunk);
+            Some((chunk, guard))
+        };
+
+        match self.scan {
+            Scan::Up(start) => {
+                let (mut from, mut wrapped) = (start, false);
+                loop {
+                    let chunk = match self.state.wanted.first_free(self.board, from) {
+                        Some(chunk) if !wrapped || chunk < start => chunk,
+                        None if !wrapped => {
+                            wrapped = true;
+                            from = 0;
+                            continue;
+                        }
+                        _ => return None,
                     };
 
-                    self.internal = Some((iter, guard));
-                    return Some(item.get());
+                    if let Some(taken) = try_lock(&mut self.state.wanted, chunk) {
+                        // Carry on from here next time.
+                        self.scan = Scan::Up(chunk + 1);
+                        return Some(taken);
+                    }
+                    from = chunk + 1;
                 }
+            }
+            Scan::Down => {
+                let mut below = usize::MAX;
+                loop {
+                    let chunk = self.state.wanted.last_free(self.board, below)?;
+                    if let Some(taken) = try_lock(&mut self.state.wanted, chunk) {
+                        return Some(taken);
+                    }
+                    below = chunk;
+                }
+            }
+        }
+    }
+
+    /// What we have to visit of a chunk we still want all
+    /// of, when it's `chunk_len` long.
+    fn visit(&self, chunk: usize, chunk_len: usize) -> ChunkVisit {
+        ChunkVisit::within(chunk, &self.range, self.slices[chunk].start(), chunk_len)
+    }
+
+    /// Unlike the tracker's, counts exactly what was appended
+    /// to the last chunk before we started.
+    fn chunk_len(&self, chunk: usize) -> usize {
+        let tracker = &self.slices[chunk];
+        if chunk == self.slices.len() - 1 {
+            // Both only change while the chunk is edited, which
+            // it can't be while it's locked, or a thief checks.
+            // Saturates once edits settled appends from after we
+            // started, and removed more than were there before.
+            (tracker.length() + self.appended_before).saturating_sub(self.settled.load(Ordering::Acquire))
+        } else {
+            tracker.length()
+        }
+    }
+
+    /// Starts on a chunk we still want all of, and just locked.
+    /// It's only looked at now, since until it was locked, its
+    /// elements could have been moved around.
+    fn start_wanted(&mut self, chunk: usize, guard: ChunkGuard<'a, Mtx>) -> Option<()> {
+        let chunk_len = self.chunk_len(chunk);
+        self.start(self.visit(chunk, chunk_len), chunk_len, guard)
+    }
+
+    /// Starts on what's left of a chunk we stole from, and just
+    /// locked. We hold on to it until we're done, so nobody can
+    /// move its elements around before then either.
+    fn start_leftover(&mut self, visit: ChunkVisit, guard: ChunkGuard<'a, Mtx>) -> Option<()> {
+        let chunk_len = self.chunk_len(visit.chunk_id);
+        self.slices[visit.chunk_id].revisits.fetch_sub(1, Ordering::SeqCst);
+        self.start(visit, chunk_len, guard)
+    }
+
+    fn start(&mut self, visit: ChunkVisit, chunk_len: usize, guard: ChunkGuard<'a, Mtx>) -> Option<()> {
+        let tracker = &self.slices[visit.chunk_id];
+        let slot = steal::can_publish(chunk_len).then(|| &tracker.steal[0]);
+        self.board.locked(visit.chunk_id);
+        self.policy.took(visit.chunk_id);
+        if self.writes {
+            tracker.writing();
+        }
+
+        let claim = Claim::Locked(guard, self.board);
+        let range = visit.local_range(chunk_len);
+        self.begin(Current::new(tracker, self.cane, visit.chunk_id, range, slot, claim, self.wait, self.released))
+    }
+
+    /// Moves on to `current`, unless there's nothing in it.
+    fn begin(&mut self, mut current: Current<'a, Mtx, T>) -> Option<()> {
+        let range = current.next_batch()?;
+        self.batch = current.slice(range).iter();
+        self.internal = Some(current);
+        Some(())
+    }
+
+    /// Borrows the back half of what's left of a busy chunk.
+    /// We still have to visit the rest of that chunk later.
+    fn steal(&mut self) -> Option<()> {
+        // A steal can leave two more parts to come back to.
+        if self.state.leftovers.remaining_capacity() < 2 {
+            return None;
+        }
+
+        let mut leftovers = self.state.leftovers.len();
+        let mut from = 0;
+        loop {
+            // Leftovers first, then every chunk we still want.
+            let (chunk, leftover) = if leftovers > 0 {
+                leftovers -= 1;
+                (self.state.leftovers[leftovers].chunk_id, Some(leftovers))
+            } else {
+                let chunk = self.state.wanted.first_wanted(from)?;
+                from = chunk + 1;
+                (chunk, None)
+            };
+
+            // If that's this thread, it would wait for us to give back
+            // what we stole, and we'd never get to. Debug builds panic
+            // once we wait for the chunk instead.
+            if deadlock::holds_chunk(self.cane, chunk) {
+                continue;
+            }
+
+            let tracker = &self.slices[chunk];
+            // Whoever we steal from holds the chunk, but may only
+            // have taken it after someone moved its elements around
+            // since we looked, in which case what we looked at is
+            // of no use.
+            let edits = tracker.edits.load(Ordering::SeqCst);
+            let chunk_len = self.chunk_len(chunk);
+            if !steal::can_publish(chunk_len) {
+                continue;
+            }
+
+            let visit = match leftover {
+                Some(index) => self.state.leftovers[index],
+                None => self.visit(chunk, chunk_len),
+            };
+            let within = visit.local_range(chunk_len);
+            let (victim, stolen) = match tracker.steal.iter().find_map(|slot| Some((slot, slot.steal(&within, self.released)?))) {
+                Some(x) => x,
+                None => continue,
+            };
+            if tracker.edits.load(Ordering::SeqCst) != edits {
+                // It visits what we stole itself anyway.
+                victim.give_back(self.released);
+                continue;
+            }
+            tracker.stats.steals.increment();
+            if self.writes {
+                // Its owner may only be reading it.
+                tracker.writing();
+            }
+
+            // Counted before we give back what we stole, and so
+            // before the chunk can next be locked by anyone.
+            let mut revisits = 0;
+            for rest in [within.start..stolen.start, stolen.end..within.end] {
+                if !rest.is_empty() {
+                    self.state.leftovers.push(ChunkVisit::from_local(chunk, rest, chunk_len));
+                    revisits += 1;
+                }
+            }
+            tracker.revisits.fetch_add(revisits, Ordering::SeqCst);
+            match leftover {
+                Some(index) => {
+                    self.state.leftovers.swap_remove(index);
+                    tracker.revisits.fetch_sub(1, Ordering::SeqCst);
+                }
+                None => self.state.wanted.remove(chunk),
+            }
 
-                None
+            // The first slot belongs to whoever holds the lock.
+            let slot = tracker.steal[1..].iter().find(|slot| slot.try_acquire());
+            let current = Current::new(tracker, self.cane, chunk, stolen, slot, Claim::Lent(victim), self.wait, self.released);
+            if self.begin(current).is_some() {
+                return Some(());
             }
         }
     }
 }
 
+impl<'a, Rw: RawRwLock, Mtx: RawMutex, T> Drop for RawCandyCaneIterStreaming<'a, Rw, Mtx, T> {
+    fn drop(&mut self) {
+        if !self.state.leftovers.is_empty() {
+            for visit in self.state.leftovers.drain(..) {
+                self.slices[visit.chunk_id].revisits.fetch_sub(1, Ordering::SeqCst);
+            }
+            // Anyone waiting for those chunks to be left alone isn't
+            // woken by them being unlocked, since we never locked them.
+            self.released.notify();
+        }
+
+        // Otherwise an adaptive cane which is rarely written would
+        // never adapt. We still hold the shared lock here.
+        if let (Some(cane), Some(_)) = (self.rebalance, &self.all_lock) {
+            cane.request_rebalance();
+        }
+    }
+}
+
 pub struct CandyCaneIterStreaming<'a, T: Sync, R: RawRwLock = RwLock, M: RawMutex = parking_lot::RawMutex> {
     pub(crate) inner: RawCandyCaneIterStreaming<'a, R, M, T>,
 }
 
 impl<'a, T: Sync, R: RawRwLock, M: RawMutex> CandyCaneIterStreaming<'a, T, R, M> {
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<&T> {
         // SAFETY: The internal iterator should only
         // ever be called with `LockGuardType::Read`
@@ -129,6 +887,23 @@ impl<'a, T: Sync, R: RawRwLock, M: RawMutex> CandyCaneIterStreaming<'a, T, R, M>
             .next_raw()
             .map(|x| unsafe { &*x })
     }
+
+    /// The rest of the current batch, for when a whole
+    /// slice can be worked on faster than one at a time.
+    #[inline]
+    pub fn next_slice(&mut self) -> Option<&[T]> {
+        // SAFETY: As above.
+        self.inner
+            .next_slice_raw()
+            .map(|x| unsafe { &*x })
+    }
+
+    /// Whether a writer rebuilt the cane's chunks while this
+    /// iterator was interrupted. Always `false` for iterators
+    /// which aren't interruptible.
+    pub fn layout_changed(&self) -> bool {
+        self.inner.layout_changed()
+    }
 }
 
 pub struct CandyCaneIterStreamingMut<'a, T: Send, R: RawRwLock = RwLock, M: RawMutex = parking_lot::RawMutex> {
@@ -137,6 +912,7 @@ pub struct CandyCaneIterStreamingMut<'a, T: Send, R: RawRwLock = RwLock, M: RawM
 
 impl<'a, T: Send, R: RawRwLock, M: RawMutex> CandyCaneIterStreamingMut<'a, T, R, M> {
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<&mut T> {
         // SAFETY: The internal iterator should only
         // ever be called with `LockGuardType::Write`
@@ -144,4 +920,18 @@ impl<'a, T: Send, R: RawRwLock, M: RawMutex> CandyCaneIterStreamingMut<'a, T, R,
             .next_raw()
             .map(|x| unsafe { &mut *x })
     }
+
+    /// See [`CandyCaneIterStreaming::next_slice`].
+    #[inline]
+    pub fn next_slice(&mut self) -> Option<&mut [T]> {
+        // SAFETY: As above.
+        self.inner
+            .next_slice_raw()
+            .map(|x| unsafe { &mut *x })
+    }
+
+    /// See [`CandyCaneIterStreaming::layout_changed`].
+    pub fn layout_changed(&self) -> bool {
+        self.inner.layout_changed()
+    }
 }
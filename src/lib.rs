@@ -1,33 +1,194 @@
 pub mod iter;
 pub mod raw;
+pub mod testing;
+mod adaptive;
+mod claim;
+mod deadlock;
+mod optimistic;
+mod padded;
+mod policy;
+mod range_guard;
+mod rcu;
 mod slice_tracker;
-
+mod stats;
+mod steal;
+mod storage;
+mod transaction;
+mod upgradable;
+mod wait;
+
+use crate::adaptive::Adaptive as AdaptiveConfig;
+use crate::claim::Board;
 use crate::iter::streaming::{CandyCaneIterStreaming, CandyCaneIterStreamingMut};
+use crate::iter::{normalize_range, RangeError};
 use crate::raw::RawCandyCaneIterStreaming;
-use crate::slice_tracker::{SliceTracker, LockGuard, LockGuardType};
-use parking_lot::lock_api::{RawRwLock, RawMutex};
+use crate::deadlock::Held;
+use crate::slice_tracker::{SliceTracker, LockGuard, LockGuardType, UpgradableGuard};
+use crate::stats::{CaneCounters, Timer};
+use crate::storage::sealed::Buffer;
+use crate::wait::Released;
+use parking_lot::lock_api::{RawRwLock, RawRwLockUpgrade, RawMutex};
 use parking_lot::{Condvar, Mutex};
 use std::cell::UnsafeCell;
-use std::mem::MaybeUninit;
+use std::mem::ManuallyDrop;
 use std::ops::{RangeBounds, DerefMut, Deref};
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 // use crate::iter::normal::{CandyCaneIter, RawCandyCaneIter, CandyCaneIterMut};
 
+pub use crate::adaptive::Adaptive;
+pub use crate::iter::streaming::{IterState, OnLayoutChange};
+pub use crate::range_guard::CandyCaneRangeGuard;
+pub use crate::rcu::{RcuCandyCane, RcuReadGuard, RcuWriteGuard};
+pub use crate::policy::{ChunkPolicy, ReverseScan, RoundRobin, Scan, Sticky, ThreadHashed};
+pub use crate::storage::{ChunkBounds, Contiguous, Segmented, Storage};
+pub use crate::transaction::CandyCaneTransaction;
+pub use crate::upgradable::CandyCaneUpgradableRead;
+pub use crate::wait::WaitStrategy;
+#[cfg(feature = "stats")]
+pub use crate::stats::{CandyCaneStats, ChunkStats};
+
 pub type CandyCane<T> = RawCandyCane<parking_lot::RawRwLock, parking_lot::RawMutex, T, 6>;
 
-/// SAFETY: Every element in `from` must be initialized
-unsafe fn assume_init_array<T, const LEN: usize>(from: [MaybeUninit<T>; LEN]) -> [T; LEN] {
-    let val = std::mem::transmute_copy::<_, [T; LEN]>(&from);
-    std::mem::forget(from);
-    val
+/// `UnsafeCell<T>` is `repr(transparent)`, so a `Vec<T>` and a
+/// `Vec<UnsafeCell<T>>` share their allocation's layout.
+fn into_cells<T>(vec: Vec<T>) -> Vec<UnsafeCell<T>> {
+    let mut vec = ManuallyDrop::new(vec);
+    // SAFETY: See above. `vec` is never touched again.
+    unsafe { Vec::from_raw_parts(vec.as_mut_ptr().cast(), vec.len(), vec.capacity()) }
 }
 
-pub struct RawCandyCane<R: RawRwLock, M: RawMutex, T, const SLICES: usize> {
-    data: UnsafeCell<Vec<UnsafeCell<T>>>,
-    slices: [UnsafeCell<SliceTracker<M, T>>; SLICES],
-    /// Self explanatory (len / SLICES).
-    len_per_slice: AtomicUsize,
+/// Where a vec's elements are, how many fit there, and how
+/// many there are. Unless the first two changed, the vec is
+/// still in the same allocation.
+fn shape<T>(vec: &Vec<T>) -> (usize, usize, usize) {
+    (vec.as_ptr() as usize, vec.capacity(), vec.len())
+}
+
+/// The inverse of `into_cells`.
+fn from_cells<T>(vec: Vec<UnsafeCell<T>>) -> Vec<T> {
+    let mut vec = ManuallyDrop::new(vec);
+    // SAFETY: See `into_cells`.
+    unsafe { Vec::from_raw_parts(vec.as_mut_ptr().cast(), vec.len(), vec.capacity()) }
+}
+
+/// A buffer split into `SLICES` chunks, each behind its own
+/// mutex, so that many threads can iterate over it at once.
+///
+/// With [`set_adaptive`](Self::set_adaptive), the number of chunks
+/// instead follows how contended they are, starting from `SLICES`.
+///
+/// `S` picks how the elements are stored, either all in one
+/// allocation or in one per chunk, see [`Storage`].
+///
+/// # Thread safety
+///
+/// The cane is `Send` when `T: Send`, and `Sync` when
+/// `T: Send + Sync`, since any thread holding a `&RawCandyCane`
+/// may get both `&T` and `&mut T` out of it.
+///
+/// ```
+/// # use candy_cane::CandyCane;
+/// # use std::cell::Cell;
+/// fn assert_send<T: Send>() {}
+/// fn assert_sync<T: Sync>() {}
+///
+/// assert_send::<CandyCane<u8>>();
+/// assert_sync::<CandyCane<u8>>();
+/// assert_send::<CandyCane<Cell<u8>>>();
+/// ```
+///
+/// Types which can't leave their thread can't be put in a
+/// cane that is sent or shared:
+///
+/// ```compile_fail,E0277
+/// # use candy_cane::CandyCane;
+/// # use std::rc::Rc;
+/// let cane = CandyCane::from_vec(vec![Rc::new(0u8)]);
+/// std::thread::spawn(move || drop(cane));
+/// ```
+///
+/// ```compile_fail,E0277
+/// # use candy_cane::CandyCane;
+/// # use std::rc::Rc;
+/// # use std::sync::Arc;
+/// let cane = Arc::new(CandyCane::from_vec(vec![Rc::new(0u8)]));
+/// let clone = Arc::clone(&cane);
+/// std::thread::spawn(move || clone.write().push(Rc::new(1)));
+/// ```
+///
+/// Nor can types that only allow one thread to look at them
+/// at a time:
+///
+/// ```compile_fail,E0277
+/// # use candy_cane::CandyCane;
+/// # use std::cell::Cell;
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<CandyCane<Cell<u8>>>();
+/// ```
+///
+/// The guards follow their locks, so the write guard from a
+/// `parking_lot` lock can't be unlocked on another thread:
+///
+/// ```compile_fail,E0277
+/// # use candy_cane::CandyCane;
+/// let cane = CandyCane::from_vec(vec![0u8]);
+/// std::thread::scope(|s| {
+///     let guard = cane.write();
+///     s.spawn(move || drop(guard));
+/// });
+/// ```
+///
+/// Neither can an iterator, since it holds chunk locks:
+///
+/// ```compile_fail,E0277
+/// # use candy_cane::CandyCane;
+/// let cane = CandyCane::from_vec(vec![0u8]);
+/// std::thread::scope(|s| {
+///     let iter = cane.iter_streaming_mut(..);
+///     s.spawn(move || drop(iter));
+/// });
+/// ```
+pub struct RawCandyCane<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage = Contiguous> {
+    data: UnsafeCell<S::Buffer<T>>,
+    /// Only ever changed under the exclusive lock, and only
+    /// replaced entirely when the cane is re-partitioned.
+    slices: UnsafeCell<Vec<SliceTracker<M, T>>>,
+    /// Which of `slices` are locked, replaced along with them.
+    board: UnsafeCell<Board>,
+    /// Only changed under the exclusive lock.
+    policy: UnsafeCell<Box<dyn ChunkPolicy>>,
+    /// As is this.
+    wait: UnsafeCell<WaitStrategy>,
+    /// Notified whenever any chunk is unlocked.
+    released: Released,
+    /// How many elements there are. Past `data.len()` once some were
+    /// appended under the shared lock, into `data`'s spare capacity.
+    /// `data` catches up the next time the cane is locked exclusively.
+    len: AtomicUsize,
+    /// Every element ever appended under the shared lock, so that
+    /// iterators can leave out those appended after they started.
+    /// Appending only counts here, and not in the last chunk.
+    appended: AtomicUsize,
+    /// How many of `appended` the last chunk's length already
+    /// counts. Moves along with that length, which is when the
+    /// chunk is edited, and when the chunks are rebuilt.
+    settled: AtomicUsize,
+    /// Counts how often the chunks were rebuilt, so that iterators
+    /// which let go of the shared lock can tell whether theirs are
+    /// still the same. Only changed under the exclusive lock.
+    generation: AtomicUsize,
+    /// Taken by everyone appending under the shared lock, and by
+    /// anyone inserting into or removing from the last chunk.
+    tail: Mutex<()>,
+    /// Only changed under the exclusive lock.
+    bounds: UnsafeCell<ChunkBounds>,
+    adaptive: Mutex<Option<AdaptiveConfig>>,
+    /// Set when an iterator finished and found the chunks due to be
+    /// decided on again, so that whoever next takes the shared lock
+    /// tries to. Iterators can't do it themselves, since they still
+    /// borrow the chunks until they're gone.
+    rebalance_pending: AtomicBool,
     // SAFETY: `all_lock` must be boxed to ensure
     // that the pointers in the `SliceTracker`s
     // remain valid even after this `RawCandyCane`
@@ -35,202 +196,414 @@ pub struct RawCandyCane<R: RawRwLock, M: RawMutex, T, const SLICES: usize> {
     all_lock: R,
     is_waiting_mut: Mutex<bool>,
     waiting_mut_wakeup: Condvar,
+    stats: CaneCounters,
 }
 
-impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> RawCandyCane<R, M, T, SLICES> {
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
     pub fn new() -> Self {
-        assert_ne!(SLICES, 0);
-
-        let data = Vec::<UnsafeCell<T>>::new();
-
-        let rwlock = R::INIT;
-
-        // SAFETY: `MaybeUninit` does not require initialization.
-        let mut slices: [MaybeUninit<UnsafeCell<SliceTracker<M, T>>>; SLICES] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-
-        for slice in &mut slices[..] {
-            // SAFETY: None of the `SliceTracker`s will overlap
-            // since they all have length 0.
-            unsafe {
-                *slice = MaybeUninit::new(UnsafeCell::new(SliceTracker::new(data.as_ptr(), 0)));
-            }
-        }
-
-        // SAFETY: `slices` was initialized by the previous
-        // `for` loop.
-        let slices = unsafe { assume_init_array(slices) };
-
-        Self {
-            data: UnsafeCell::new(data),
-            slices,
-            len_per_slice: AtomicUsize::new(0),
-            all_lock: rwlock,
-            is_waiting_mut: Mutex::new(false),
-            waiting_mut_wakeup: Condvar::new(),
-        }
+        Self::from_vec(Vec::new())
     }
 
-    pub fn from_vec(mut data: Vec<T>) -> Self {
+    pub fn from_vec(data: Vec<T>) -> Self {
         assert_ne!(SLICES, 0);
 
-        if data.len() == 0 {
-            return Self::new();
-        }
-
-        let data = {
-            let len = data.len();
-            let ptr = data.as_mut_ptr() as *mut UnsafeCell<T>;
-            let cap = data.capacity();
-
-            std::mem::forget(data);
-
-            unsafe { Vec::from_raw_parts(ptr, len, cap) }
-        };
+        let data = S::Buffer::from_vec(into_cells(data), SLICES);
 
         let rwlock = R::INIT;
 
-        let (slices, per_slice) = Self::create_slices(&data);
+        let slices = Self::create_slices(Self::layout(&data, SLICES), false);
 
         Self {
+            len: AtomicUsize::new(data.len()),
+            appended: AtomicUsize::new(0),
+            settled: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            tail: Mutex::new(()),
+            bounds: UnsafeCell::new(ChunkBounds::default()),
             data: UnsafeCell::new(data),
-            len_per_slice: AtomicUsize::new(per_slice),
-            slices,
+            board: UnsafeCell::new(Board::new(slices.len())),
+            slices: UnsafeCell::new(slices),
+            policy: UnsafeCell::new(Box::new(ReverseScan)),
+            wait: UnsafeCell::new(WaitStrategy::default()),
+            released: Released::default(),
+            adaptive: Mutex::new(None),
+            rebalance_pending: AtomicBool::new(false),
             all_lock: rwlock,
             is_waiting_mut: Mutex::new(false),
             waiting_mut_wakeup: Condvar::new(),
+            stats: CaneCounters::default(),
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len(&self) -> usize {
+        // Waits for any writer, whose vec could be any length.
         let lock = self.lock_internal_for_read();
-
-        // SAFETY: `all_lock` is shared currently,
-        // and therefore no writes directly to the
-        // vec should be occurring, making .len()
-        // a safe operation.
-        let len = unsafe { (&*self.data.get()).len() };
-
+        let len = self.len.load(Ordering::Acquire);
         drop(lock);
 
         len
     }
 
-    pub fn write(&self) -> CandyCaneWriteGuard<R, M, T, SLICES> {
-        *self.is_waiting_mut.lock() = true;
-        let guard = LockGuard::lock(&self.all_lock, LockGuardType::Write);
-        self.waiting_mut_wakeup.notify_all();
-
-        let vec = self.data.get();
-        let reconstructed_vec = unsafe {
-            let ptr = (*vec).as_mut_ptr().cast::<T>();
-            let len = (*vec).len();
-            let cap = (*vec).capacity();
+    /// # Panics
+    ///
+    /// In debug builds, if this thread is holding an iterator
+    /// or write guard for this cane, since this would never
+    /// return otherwise.
+    ///
+    /// Dropping the guard only updates the chunks if the vec
+    /// was moved or resized, or the cane wants a different
+    /// number of them, so changing values alone costs nothing
+    /// once the lock is held.
+    ///
+    /// With [`Segmented`] storage, this moves every element
+    /// into one vec, and back out into chunks once it's dropped.
+    pub fn write(&self) -> CandyCaneWriteGuard<'_, R, M, T, SLICES, S> {
+        let guard = self.lock_internal_for_write();
+        self.write_guard(guard)
+    }
 
-            Vec::from_raw_parts(ptr, len, cap)
-        };
+    /// Hands the elements over to a write guard. `guard` must be
+    /// the exclusive lock.
+    fn write_guard<'a>(&'a self, guard: LockGuard<'a, R>) -> CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
+        // SAFETY: `all_lock` is exclusive, so nothing else can be
+        // looking at the buffer. It's moved out so that the guard
+        // is its only owner until it is put back on drop.
+        let reconstructed_vec = from_cells(unsafe { self.settled(&guard).take() });
 
         CandyCaneWriteGuard {
+            _hold: self.stats.write_hold_nanos.time(),
             lock: guard,
+            before: shape(&reconstructed_vec),
             vec: reconstructed_vec,
             original: self,
             _phantom: PhantomData,
         }
     }
 
-    pub fn into_inner(self) -> Vec<T> {
+    /// Like [`write`](Self::write), except that the guard edits a
+    /// copy of the elements, which only replaces them once it's
+    /// [committed](CandyCaneTransaction::commit). Dropping it, or
+    /// calling `abort`, leaves the cane exactly as it was, down to
+    /// how it's split into chunks.
+    ///
+    /// # Panics
+    ///
+    /// Like `write`, and whenever cloning an element panics,
+    /// which leaves the cane as it was too.
+    pub fn write_transaction(&self) -> CandyCaneTransaction<'_, R, M, T, SLICES, S>
+    where
+        T: Clone,
+    {
+        let guard = self.lock_internal_for_write();
+        let hold = self.stats.write_hold_nanos.time();
+
+        // SAFETY: `guard` is exclusive. The buffer is only looked
+        // at, so that aborting leaves everything where it was.
+        let data = unsafe { self.settled(&guard) };
+        let mut vec = Vec::with_capacity(data.len());
+        for (part, length) in data.parts(self.current_chunks()) {
+            // SAFETY: The parts cover every element, and all of
+            // them have been written.
+            let part = unsafe { std::slice::from_raw_parts(part, length) };
+            // SAFETY: Nothing else can be looking at them.
+            vec.extend(part.iter().map(|x| unsafe { &*x.get() }.clone()));
+        }
+
+        CandyCaneTransaction::new(hold, guard, self, vec)
+    }
+
+    /// How many chunks the cane is currently split into.
+    pub fn chunk_count(&self) -> usize {
+        let _lock = self.lock_internal_for_read();
+        self.current_chunks()
+    }
+
+    /// Lets the cane split and merge its chunks by itself,
+    /// based on how often iterators had to wait for them and
+    /// how long they were held for. With `None`, it goes back
+    /// to `SLICES` chunks.
+    ///
+    /// The chunks can only change while the cane is locked
+    /// exclusively, so this happens whenever a write guard is
+    /// dropped, on [`rebalance`](Self::rebalance), or after an
+    /// iterator finishes, the next time the cane is locked while
+    /// nothing else holds it.
+    pub fn set_adaptive(&self, adaptive: Option<AdaptiveConfig>) {
+        let guard = self.write();
+        *self.adaptive.lock() = adaptive;
+        // Dropping the guard rebuilds the chunks.
+        drop(guard);
+    }
+
+    /// Changes where iterators look first for a free chunk,
+    /// which by default is [`ReverseScan`]. Waits for every
+    /// iterator to finish first.
+    pub fn set_chunk_policy(&self, policy: impl ChunkPolicy) {
+        let guard = self.write();
+        // SAFETY: `guard` is exclusive, so no iterator can be
+        // looking at the policy.
+        unsafe { *self.policy.get() = Box::new(policy) };
+        drop(guard);
+    }
+
+    /// Changes what iterators do once every chunk they have
+    /// left is busy. Waits for every iterator to finish first.
+    pub fn set_wait_strategy(&self, wait: WaitStrategy) {
+        let guard = self.write();
+        // SAFETY: See `set_chunk_policy`.
+        unsafe { *self.wait.get() = wait };
+        drop(guard);
+    }
+
+    /// Gives an adaptive cane a chance to re-partition itself,
+    /// for workloads where iterators rarely find the cane to
+    /// themselves, and which rarely call `write()` otherwise.
+    pub fn rebalance(&self) {
+        drop(self.write());
+    }
+
+    pub fn into_inner(mut self) -> Vec<T> {
         // Sanity check
         *self.is_waiting_mut.lock() = true;
         LockGuard::try_lock(&self.all_lock, LockGuardType::Write).unwrap();
 
-        let mut original = self.data.into_inner();
-        let reconstructed_vec = unsafe {
-            let ptr = original.as_mut_ptr().cast::<T>();
-            let len = original.len();
-            let cap = original.capacity();
+        let data = self.settled_mut().take();
+        *self.len.get_mut() = 0;
+        from_cells(data)
+    }
+
+    /// The buffer, caught up with everything appended since.
+    /// The guard must be exclusive, and for this cane.
+    ///
+    /// SAFETY: The buffer must not be looked at through any
+    /// other reference while the returned one is alive.
+    unsafe fn settled<'a>(&'a self, guard: &LockGuard<'a, R>) -> &'a mut S::Buffer<T> {
+        assert!(self.ensure_my_write_guard(guard));
+        let data = &mut *self.data.get();
+        // Everything up to `len` was written before it was published.
+        data.settle(self.len.load(Ordering::Acquire));
+        data
+    }
+
+    /// Like `settled`, for when `&mut self` rules out any locks.
+    fn settled_mut(&mut self) -> &mut S::Buffer<T> {
+        let len = *self.len.get_mut();
+        let data = self.data.get_mut();
+        // SAFETY: See `settled`.
+        unsafe { data.settle(len) };
+        data
+    }
+
+    /// Takes the exclusive lock, for `write()` or anything
+    /// else which changes how the elements are laid out.
+    fn lock_internal_for_write(&self) -> LockGuard<'_, R> {
+        deadlock::check_write(deadlock::id_of(&self.all_lock));
+        self.close_gate_for(|| LockGuard::lock(&self.all_lock, LockGuardType::Write))
+    }
 
-            Vec::from_raw_parts(ptr, len, cap)
+    /// Keeps new readers out while `lock` waits for the exclusive
+    /// lock, so that they can't starve it.
+    fn close_gate_for<'a>(&'a self, lock: impl FnOnce() -> LockGuard<'a, R>) -> LockGuard<'a, R> {
+        self.stats.writes.increment();
+        let guard = {
+            let _waiting = self.stats.write_wait_nanos.time();
+            *self.is_waiting_mut.lock() = true;
+            lock()
         };
+        // Readers arriving from now on will block on `all_lock`
+        // instead, so the gate can be reopened.
+        *self.is_waiting_mut.lock() = false;
+        self.waiting_mut_wakeup.notify_all();
+
+        guard
+    }
+
+    /// How many chunks there are. `all_lock` must be held.
+    fn current_chunks(&self) -> usize {
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        unsafe { (*self.slices.get()).len() }
+    }
+
+    /// How many chunks the cane should be rebuilt with. Must
+    /// be called under the exclusive lock.
+    fn chunks_wanted(&self, len: usize) -> usize {
+        // SAFETY: Nothing else can be looking at the trackers.
+        let slices = unsafe { &*self.slices.get() };
+        match *self.adaptive.lock() {
+            Some(adaptive) => {
+                let windows = slices.iter().filter_map(|x| x.window.as_ref());
+                adaptive.decide(slices.len(), len, windows).unwrap_or(slices.len())
+            }
+            None => SLICES,
+        }
+    }
 
-        std::mem::forget(original);
+    /// Whether there are `chunks` chunks already, made for the
+    /// current adaptiveness. `all_lock` must be held exclusively.
+    fn chunks_kept(&self, chunks: usize) -> bool {
+        // SAFETY: Nothing else can be looking at the trackers.
+        let slices = unsafe { &*self.slices.get() };
+        slices.len() == chunks && slices[0].window.is_some() == self.adaptive.lock().is_some()
+    }
 
-        reconstructed_vec
+    /// Rebuilds the trackers of every chunk which changed, with
+    /// `chunks` of them if the storage lets elements move.
+    pub(crate) fn reconstruct_chunks<'a>(&'a self, lock: &LockGuard<'a, R>, chunks: usize) {
+        self.rebuild_chunks(lock, chunks, true);
     }
 
-    pub(crate) fn reconstruct_chunks<'a>(&'a self, lock: &LockGuard<'a, R>) {
-        assert!(self.ensure_my_write_guard(&lock));
+    /// Like `reconstruct_chunks`. Unless `moved`, the elements are
+    /// still in the allocations they were in when the trackers were
+    /// last rebuilt, so those of chunks which start and end where
+    /// they did are left alone.
+    fn rebuild_chunks<'a>(&'a self, lock: &LockGuard<'a, R>, chunks: usize, moved: bool) {
+        assert!(self.ensure_my_write_guard(lock));
 
         // SAFETY: We ensured that the lock we were given is for our lock.
         let data = unsafe { &*self.data.get() };
 
-        let (slices, per_chunk) = Self::create_slices(&data);
-
-        for (dest, src) in self.slices.iter().zip(std::array::IntoIter::new(slices)) {
-            unsafe {
-                *dest.get() = src.into_inner();
+        // SAFETY: As above, and no iterator can be alive to be
+        // looking at the trackers.
+        let slices = unsafe { &mut *self.slices.get() };
+
+        let adaptive = self.adaptive.lock().is_some();
+        let parts = Self::layout(data, chunks);
+
+        // Only the fields describing the chunks are replaced, so
+        // that their counters keep counting across writes, and
+        // new trackers are only made for chunks that are new.
+        if adaptive != slices[0].window.is_some() {
+            // SAFETY: As above.
+            unsafe { *self.board.get() = Board::new(parts.len()) };
+            *slices = Self::create_slices(parts, adaptive);
+        } else if parts.len() == slices.len() {
+            for (dest, (part, start, length)) in slices.iter_mut().zip(parts) {
+                // Like every chunk but the last one, when only
+                // a few elements were pushed.
+                if !moved && (dest.start(), dest.length()) == (start, length) {
+                    continue;
+                }
+                *dest.data.get_mut() = part.cast_mut();
+                *dest.start.get_mut() = start;
+                *dest.length.get_mut() = length;
             }
+        } else {
+            // Chunks which still start with the same element, like
+            // those before one that was split, merged or appended
+            // to, keep their trackers.
+            let mut old = std::mem::take(slices).into_iter().peekable();
+            *slices = parts
+                .into_iter()
+                .map(|(part, start, length)| {
+                    while old.next_if(|x| x.start() < start).is_some() {}
+                    // Kept by where it starts, not by its address, which
+                    // may belong to a new allocation now. Its pointer is
+                    // always re-stored, so that it has the new provenance.
+                    match old.next_if(|x| x.start() == start) {
+                        Some(mut kept) => {
+                            *kept.data.get_mut() = part.cast_mut();
+                            *kept.length.get_mut() = length;
+                            kept
+                        }
+                        None => Self::create_slice(part, start, length, adaptive),
+                    }
+                })
+                .collect();
+            // SAFETY: As above.
+            unsafe { *self.board.get() = Board::new(slices.len()) };
         }
 
-        // Does ordering matter here? Since nothing should
-        // be reading this value right now.
-        self.len_per_slice.store(per_chunk, Ordering::Release);
+        self.settled.store(self.appended.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.len.store(data.len(), Ordering::Release);
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn create_slices(data: &Vec<UnsafeCell<T>>) -> ([UnsafeCell<SliceTracker<M, T>>; SLICES], usize) {
-        // SAFETY: `MaybeUninit` does not require initialization.
-        let mut slices: [MaybeUninit<UnsafeCell<SliceTracker<M, T>>>; SLICES] =
-            unsafe { MaybeUninit::uninit().assume_init() };
+    /// How long the last chunk is, with everything appended to it.
+    /// The tail lock must be held, or the exclusive lock.
+    fn last_len(&self, last: &SliceTracker<M, T>) -> usize {
+        last.length() + self.appended.load(Ordering::Acquire) - self.settled.load(Ordering::Relaxed)
+    }
 
-        let per_slice = data.len() / SLICES;
-        let last_extra = data.len() % SLICES;
-        let mut running_total = 0;
+    /// Where each chunk of `data` starts in memory and in the
+    /// cane, and how long it is.
+    fn layout(data: &S::Buffer<T>, chunks: usize) -> Vec<(*const UnsafeCell<T>, usize, usize)> {
+        let mut start = 0;
+        data.parts(chunks)
+            .into_iter()
+            .map(|(part, length)| {
+                start += length;
+                (part, start - length, length)
+            })
+            .collect()
+    }
 
-        for slice in &mut slices[..SLICES - 1] {
-            // SAFETY: None of the `SliceTracker`s should overlap
-            unsafe {
-                *slice = MaybeUninit::new(UnsafeCell::new(SliceTracker::new(
-                    data.as_ptr().add(running_total),
-                    per_slice,
-                )));
-            }
+    fn create_slices(parts: Vec<(*const UnsafeCell<T>, usize, usize)>, adaptive: bool) -> Vec<SliceTracker<M, T>> {
+        parts
+            .into_iter()
+            .map(|(part, start, length)| Self::create_slice(part, start, length, adaptive))
+            .collect()
+    }
 
-            running_total += per_slice;
-        }
+    fn create_slice(part: *const UnsafeCell<T>, start: usize, length: usize, adaptive: bool) -> SliceTracker<M, T> {
+        // SAFETY: Each part starts where the previous one
+        // ended, so none of them overlap.
+        unsafe { SliceTracker::new(part, start, length, adaptive) }
+    }
 
-        // SAFETY: None of the `SliceTracker`s should overlap with
-        // the last one.
-        unsafe {
-            slices[SLICES - 1] = MaybeUninit::new(UnsafeCell::new(SliceTracker::new(
-                data.as_ptr().add(running_total),
-                per_slice + last_extra,
-            )));
+    pub(crate) fn lock_internal_for_read(&self) -> LockGuard<'_, R> {
+        let cane = deadlock::id_of(&self.all_lock);
+        // Always `None` in release builds.
+        let nested = deadlock::check_read(cane);
+        if nested.is_none() && self.rebalance_pending.load(Ordering::Relaxed) {
+            self.try_rebalance();
         }
+        self.pass_gate(cane, nested);
 
-        // SAFETY: `slices` was initialized by the previous
-        // `for` loop.
-        let slices = unsafe { assume_init_array(slices) };
+        if let Some(held) = nested {
+            // A writer may have queued up since we passed the gate,
+            // in which case blocking would wait for ourselves.
+            return LockGuard::try_lock(&self.all_lock, LockGuardType::Read)
+                .unwrap_or_else(|| deadlock::writer_waiting(cane, held));
+        }
 
-        (slices, per_slice)
+        LockGuard::lock(&self.all_lock, LockGuardType::Read)
     }
 
-    pub(crate) fn lock_internal_for_read(&self) -> LockGuard<'_, R> {
+    /// Waits until no writer is waiting for the exclusive lock.
+    /// `nested` is the lock this thread already holds, if any,
+    /// which that writer would be waiting for in turn.
+    fn pass_gate(&self, cane: usize, nested: Option<Held>) {
         let mut lock = self.is_waiting_mut.lock();
+        if *lock {
+            self.stats.readers_gated.increment();
+        }
         while *lock {
+            if let Some(held) = nested {
+                deadlock::writer_waiting(cane, held);
+            }
             self.waiting_mut_wakeup.wait(&mut lock);
         }
 
-        // This is safe because lock is still alive, and another thread
-        // could not have already started to request
-        LockGuard::lock(&self.all_lock, LockGuardType::Read)
+        // The gate must be released before blocking on `all_lock`,
+        // since a writer which acquired `all_lock` first will need
+        // it to reopen the gate.
+        drop(lock);
     }
 
+    /// The chunk that holds the element at `index`. The last
+    /// chunk also holds anything appended since it was built.
+    ///
+    /// `all_lock` must be held.
     pub(crate) fn calc_slice_index(&self, index: usize) -> usize {
-        let len_per_slice = self.len_per_slice.load(Ordering::Acquire);
-        if len_per_slice == 0 {
-            SLICES
-        } else {
-            index / len_per_slice
-        }
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        let slices = unsafe { &*self.slices.get() };
+        // Empty chunks share their start with the next one,
+        // so this is the last chunk starting at or before it.
+        slices.partition_point(|x| x.start() <= index) - 1
     }
 
     fn ensure_my_write_guard<'a>(&'a self, guard: &LockGuard<'a, R>) -> bool {
@@ -239,10 +612,119 @@ impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> RawCandyCane<R, M, T, SL
     }
 }
 
-impl<R: RawRwLock, M: RawMutex, T: Sync, const SLICES: usize> RawCandyCane<R, M, T, SLICES> {
+impl<R: RawRwLockUpgrade, M: RawMutex, T, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// Reads the cane with the option of [upgrading] to a write
+    /// guard later, without any other writer getting in first.
+    ///
+    /// Other threads can keep reading, and editing what they can
+    /// edit under a shared lock, while this is held. Only writers,
+    /// and other upgradable reads, wait for it.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if this thread is holding an iterator or
+    /// guard for this cane, since upgrading would never return.
+    /// Iterate through the returned value instead.
+    ///
+    /// [upgrading]: CandyCaneUpgradableRead::upgrade
+    pub fn upgradable_read(&self) -> CandyCaneUpgradableRead<'_, R, M, T, SLICES, S> {
+        let cane = deadlock::id_of(&self.all_lock);
+        deadlock::check_upgradable(cane);
+        // Like readers, it lets waiting writers go first.
+        self.pass_gate(cane, None);
+
+        CandyCaneUpgradableRead::new(UpgradableGuard::lock(&self.all_lock), self)
+    }
+
+    pub(crate) fn upgrade_internal<'a>(&'a self, lock: UpgradableGuard<'a, R>) -> CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
+        deadlock::check_upgrade(deadlock::id_of(&self.all_lock));
+        // Readers are kept out for as long as we wait for the
+        // ones already in, the same as for `write()`.
+        let guard = self.close_gate_for(|| lock.upgrade());
+        self.write_guard(guard)
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// A snapshot of how contended the cane has been since it
+    /// was created or last reset. Waits for any writer first.
+    ///
+    /// The per chunk counters start over whenever an adaptive
+    /// cane changes how many chunks it has, except for chunks
+    /// which still start at the same element.
+    pub fn stats(&self) -> CandyCaneStats {
+        let _lock = self.lock_internal_for_read();
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        let slices = unsafe { &*self.slices.get() };
+        self.stats.snapshot(slices.iter().map(|x| x.stats.snapshot()).collect())
+    }
+
+    pub fn reset_stats(&self) {
+        let _lock = self.lock_internal_for_read();
+        // SAFETY: See `stats`.
+        let slices = unsafe { &*self.slices.get() };
+        slices.iter().for_each(|x| x.stats.reset());
+        self.stats.reset();
+    }
+}
+
+impl<R: RawRwLock, M: RawMutex, T: Sync, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// Panics if `range` is out of bounds, like indexing a `[T]` would.
     pub fn iter_streaming(&self, range: impl RangeBounds<usize>) -> CandyCaneIterStreaming<'_, T, R, M> {
-        let internal = RawCandyCaneIterStreaming::new_over(range, self);
-        CandyCaneIterStreaming { inner: internal }
+        self.try_iter_streaming(range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming(&self, range: impl RangeBounds<usize>) -> Result<CandyCaneIterStreaming<'_, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_over(range, self)?;
+        Ok(CandyCaneIterStreaming { inner: internal.read_only() })
+    }
+
+    /// Like [`iter_streaming`](Self::iter_streaming), but keeps track
+    /// of the chunks it has left in `state`, which can be reused by
+    /// the next iterator. Starting one then doesn't allocate even
+    /// when there are too many chunks for [`IterState`] to keep
+    /// track of without.
+    pub fn iter_streaming_with<'a>(&'a self, state: &'a mut IterState, range: impl RangeBounds<usize>) -> CandyCaneIterStreaming<'a, T, R, M> {
+        self.try_iter_streaming_with(state, range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_with<'a>(
+        &'a self,
+        state: &'a mut IterState,
+        range: impl RangeBounds<usize>,
+    ) -> Result<CandyCaneIterStreaming<'a, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_over_with(range, self, state)?;
+        Ok(CandyCaneIterStreaming { inner: internal.read_only() })
+    }
+
+    /// Like [`iter_streaming`](Self::iter_streaming), except that
+    /// a writer doesn't have to wait for it to finish. Whenever one
+    /// is waiting, the iterator lets go of the shared lock once it's
+    /// done with its chunk, and takes it again after the writer.
+    ///
+    /// Writers which change values, but not how many there are, keep
+    /// the chunks, so the iterator carries on where it was. Otherwise
+    /// it goes by `on_change`, and [`layout_changed`] tells whether
+    /// that happened.
+    ///
+    /// It only stops between chunks, and not while it still has to
+    /// come back for parts of one, so a writer may still have to wait
+    /// for a chunk or two. Nothing it handed out can be held on to
+    /// while it does, since each element only lives until `next`.
+    ///
+    /// [`layout_changed`]: CandyCaneIterStreaming::layout_changed
+    pub fn iter_streaming_interruptible(&self, range: impl RangeBounds<usize>, on_change: OnLayoutChange) -> CandyCaneIterStreaming<'_, T, R, M> {
+        self.try_iter_streaming_interruptible(range, on_change).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_interruptible(
+        &self,
+        range: impl RangeBounds<usize>,
+        on_change: OnLayoutChange,
+    ) -> Result<CandyCaneIterStreaming<'_, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_interruptible(range, self, on_change)?;
+        Ok(CandyCaneIterStreaming { inner: internal.read_only() })
     }
 
     // pub fn iter(&self, range: impl RangeBounds<usize>) -> CandyCaneIter<'_, T, R, M> {
@@ -251,10 +733,114 @@ impl<R: RawRwLock, M: RawMutex, T: Sync, const SLICES: usize> RawCandyCane<R, M,
     // }
 }
 
-impl<R: RawRwLock, M: RawMutex, T: Send, const SLICES: usize> RawCandyCane<R, M, T, SLICES> {
+impl<R: RawRwLock, M: RawMutex, T: Send, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// Panics if `range` is out of bounds, like indexing a `[T]` would.
     pub fn iter_streaming_mut(&self, range: impl RangeBounds<usize>) -> CandyCaneIterStreamingMut<'_, T, R, M> {
-        let internal = RawCandyCaneIterStreaming::new_over(range, self);
-        CandyCaneIterStreamingMut { inner: internal }
+        self.try_iter_streaming_mut(range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_mut(&self, range: impl RangeBounds<usize>) -> Result<CandyCaneIterStreamingMut<'_, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_over(range, self)?;
+        Ok(CandyCaneIterStreamingMut { inner: internal })
+    }
+
+    /// See [`iter_streaming_interruptible`](Self::iter_streaming_interruptible).
+    pub fn iter_streaming_mut_interruptible(&self, range: impl RangeBounds<usize>, on_change: OnLayoutChange) -> CandyCaneIterStreamingMut<'_, T, R, M> {
+        self.try_iter_streaming_mut_interruptible(range, on_change).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_mut_interruptible(
+        &self,
+        range: impl RangeBounds<usize>,
+        on_change: OnLayoutChange,
+    ) -> Result<CandyCaneIterStreamingMut<'_, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_interruptible(range, self, on_change)?;
+        Ok(CandyCaneIterStreamingMut { inner: internal })
+    }
+
+    /// Appends `value`, without waiting for iterators to finish
+    /// if there's spare capacity for it (see [`reserve`]). Only
+    /// iterators started after this returns will visit it.
+    ///
+    /// Without spare capacity this falls back to [`write`], which
+    /// has the same panics.
+    ///
+    /// [`reserve`]: Self::reserve
+    /// [`write`]: Self::write
+    pub fn push(&self, value: T) {
+        self.extend(std::iter::once(value));
+    }
+
+    /// Like [`push`](Self::push), for each of `values`. Only those
+    /// which don't fit in the spare capacity wait for the exclusive
+    /// lock, like [`write`] does.
+    ///
+    /// Appended elements belong to the last chunk, until the chunks
+    /// are next rebuilt. [`Contiguous`] canes rebuild every chunk once
+    /// they run out of capacity, and [`Segmented`] ones start a new
+    /// chunk instead, without moving any elements.
+    ///
+    /// [`write`]: Self::write
+    pub fn extend(&self, values: impl IntoIterator<Item = T>) {
+        let mut values = values.into_iter();
+        {
+            let _guard = self.lock_internal_for_read();
+            let _tail = self.tail.lock();
+            // SAFETY: `_guard` is shared, so the buffer can't be
+            // reallocated, and nobody looks past the last chunk.
+            let (data, slices) = unsafe { (&*self.data.get(), &*self.slices.get()) };
+
+            let last_len = self.last_len(&slices[slices.len() - 1]);
+            let (mut spare, room) = data.spare(self.len.load(Ordering::Relaxed), last_len);
+            for _ in 0..room {
+                let value = match values.next() {
+                    Some(value) => value,
+                    None => return,
+                };
+                // SAFETY: `spare` is within the allocation, and only
+                // we can write past `len` while we hold `tail`.
+                unsafe {
+                    spare.write(UnsafeCell::new(value));
+                    spare = spare.add(1);
+                }
+                // One at a time, so that nothing is lost if
+                // `values` panics.
+                self.appended.fetch_add(1, Ordering::Release);
+                self.len.fetch_add(1, Ordering::Release);
+            }
+        }
+
+        let mut values = values.peekable();
+        if values.peek().is_some() {
+            let guard = self.lock_internal_for_write();
+            // SAFETY: `guard` is exclusive.
+            unsafe { self.settled(&guard) }.extend_from(values);
+            self.reconstruct_chunks(&guard, self.current_chunks());
+        }
+    }
+
+    /// Makes room for at least `additional` more elements to be
+    /// appended without waiting for iterators. Waits for every
+    /// iterator to finish first, like [`write`](Self::write).
+    pub fn reserve(&self, additional: usize) {
+        let guard = self.lock_internal_for_write();
+        // SAFETY: `guard` is exclusive.
+        unsafe { self.settled(&guard) }.reserve(additional);
+        self.reconstruct_chunks(&guard, self.current_chunks());
+    }
+
+    /// See [`iter_streaming_with`](Self::iter_streaming_with).
+    pub fn iter_streaming_mut_with<'a>(&'a self, state: &'a mut IterState, range: impl RangeBounds<usize>) -> CandyCaneIterStreamingMut<'a, T, R, M> {
+        self.try_iter_streaming_mut_with(state, range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_mut_with<'a>(
+        &'a self,
+        state: &'a mut IterState,
+        range: impl RangeBounds<usize>,
+    ) -> Result<CandyCaneIterStreamingMut<'a, T, R, M>, RangeError> {
+        let internal = RawCandyCaneIterStreaming::new_over_with(range, self, state)?;
+        Ok(CandyCaneIterStreamingMut { inner: internal })
     }
 
     // pub fn iter_mut(&self, range: impl RangeBounds<usize>) -> CandyCaneIterMut<'_, T, R, M> {
@@ -263,17 +849,283 @@ impl<R: RawRwLock, M: RawMutex, T: Send, const SLICES: usize> RawCandyCane<R, M,
     // }
 }
 
-unsafe impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> Sync for RawCandyCane<R, M, T, SLICES> {}
-unsafe impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> Send for RawCandyCane<R, M, T, SLICES> {}
+/// Only contiguous canes have all of their elements in one slice.
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> RawCandyCane<R, M, T, SLICES, Contiguous> {
+    /// The whole buffer, without taking any locks, since
+    /// `&mut self` already rules out anyone else using it.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let data = self.settled_mut();
+        // SAFETY: See `into_cells`.
+        unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr().cast(), data.len()) }
+    }
+
+    /// Like [`iter_streaming_mut`](Self::iter_streaming_mut) over
+    /// the whole buffer, but without taking any locks.
+    pub fn iter_mut_exclusive(&mut self) -> std::slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+}
+
+impl<R: RawRwLock, M: RawMutex, T: Send, const SLICES: usize> RawCandyCane<R, M, T, SLICES, Contiguous> {
+    /// Locks only the chunks `range` covers, so that iterators
+    /// over the rest of the cane can carry on, unlike with
+    /// [`write`](Self::write). The elements can't be added to or
+    /// removed, only changed.
+    ///
+    /// Panics if `range` is out of bounds, like indexing a `[T]`
+    /// would, and in debug builds if this thread holds any of the
+    /// chunks already, or a write guard.
+    pub fn write_range(&self, range: impl RangeBounds<usize>) -> CandyCaneRangeGuard<'_, T, R, M> {
+        self.try_write_range(range).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-pub struct CandyCaneWriteGuard<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize> {
+    pub fn try_write_range(&self, range: impl RangeBounds<usize>) -> Result<CandyCaneRangeGuard<'_, T, R, M>, RangeError> {
+        let guard = self.lock_internal_for_read();
+
+        // SAFETY: `guard` is shared, so the vec can't be reallocated,
+        // and the trackers and board can't be changed.
+        let (data, slices, board) = unsafe { (&*self.data.get(), &*self.slices.get(), &*self.board.get()) };
+        let range = normalize_range(&range, self.len.load(Ordering::Acquire))?;
+
+        let ids = if range.is_empty() {
+            0..0
+        } else {
+            self.calc_slice_index(range.start)..self.calc_slice_index(range.end - 1) + 1
+        };
+
+        // In ascending order, which everyone taking
+        // more than one chunk at a time sticks to.
+        let cane = deadlock::id_of(&self.all_lock);
+        let chunks = ids.clone().map(|chunk| slices[chunk].lock(cane, chunk, &self.released)).collect();
+        ids.clone().for_each(|chunk| slices[chunk].writing());
+
+        // SAFETY: Everything up to `len` has been written, even past
+        // `data.len()`, and it can't be reallocated under `guard`.
+        let data = unsafe { std::slice::from_raw_parts(data.as_ptr().add(range.start), range.len()) };
+        Ok(CandyCaneRangeGuard::new(data, board, ids, chunks, guard))
+    }
+}
+
+/// Only segmented canes can move elements around within one chunk,
+/// without moving those in every other chunk as well.
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize> RawCandyCane<R, M, T, SLICES, Segmented> {
+    /// Inserts `value` at `index`, moving everything after it along
+    /// by one, like [`Vec::insert`]. Only the chunk it goes in is
+    /// locked, so iterators over the rest of the cane carry on.
+    ///
+    /// Iterators which stole part of that chunk must have come back
+    /// for the rest of it first, so this waits for them. Those which
+    /// haven't got to the chunk yet see it with `value` in it.
+    ///
+    /// Splits the chunk if it's grown too long, see [`ChunkBounds`].
+    ///
+    /// # Panics
+    ///
+    /// If `index > len`, and in debug builds if this thread holds
+    /// the chunk already, or a write guard.
+    pub fn insert(&self, index: usize, value: T) {
+        self.edit(index, true, |segment, at| segment.insert(at, UnsafeCell::new(value)));
+    }
+
+    /// Removes and returns the element at `index`, moving everything
+    /// after it back by one, like [`Vec::remove`]. Only locks the
+    /// chunk it's in, like [`insert`](Self::insert), and merges it
+    /// into a neighbour if it's grown too short.
+    ///
+    /// # Panics
+    ///
+    /// If `index >= len`, and like `insert` otherwise.
+    pub fn remove(&self, index: usize) -> T {
+        self.edit(index, false, |segment, at| segment.remove(at)).into_inner()
+    }
+
+    /// Removes and returns the element at `index`, putting the last
+    /// element of its chunk in its place. Unlike [`Vec::swap_remove`],
+    /// that isn't the last element of the whole cane, so that only
+    /// the one chunk has to be locked.
+    ///
+    /// # Panics
+    ///
+    /// Like [`remove`](Self::remove).
+    pub fn swap_remove(&self, index: usize) -> T {
+        self.edit(index, false, |segment, at| segment.swap_remove(at)).into_inner()
+    }
+
+    /// Changes when chunks are split and merged. Waits for every
+    /// iterator to finish first.
+    pub fn set_chunk_bounds(&self, bounds: ChunkBounds) {
+        let guard = self.write();
+        // SAFETY: See `set_chunk_policy`.
+        unsafe { *self.bounds.get() = bounds };
+        drop(guard);
+    }
+
+    /// Runs `edit` on the segment `index` is in, or would go in when
+    /// `inserting`, with where it is in there, under that chunk's lock.
+    /// It must insert or remove exactly one element.
+    fn edit<U>(&self, index: usize, inserting: bool, edit: impl FnOnce(&mut Vec<UnsafeCell<T>>, usize) -> U) -> U {
+        let guard = self.lock_internal_for_read();
+        // SAFETY: `guard` is shared, so no segment can be added or
+        // taken away, and the trackers and board can't be changed.
+        let (data, slices, board, bounds) =
+            unsafe { (&*self.data.get(), &*self.slices.get(), &*self.board.get(), *self.bounds.get()) };
+        let cane = deadlock::id_of(&self.all_lock);
+
+        let (result, chunk, chunk_len) = loop {
+            let len = self.len.load(Ordering::Acquire);
+            if inserting {
+                assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+            } else {
+                assert!(index < len, "removal index (is {}) should be < len (is {})", index, len);
+            }
+
+            let chunk = self.calc_slice_index(index);
+            let tracker = &slices[chunk];
+            let lock = tracker.try_lock(cane, chunk, &self.released).unwrap_or_else(|| tracker.lock(cane, chunk, &self.released));
+            if tracker.revisits.load(Ordering::SeqCst) != 0 {
+                drop(lock);
+                // Only read now, since unlocking the chunk notifies too.
+                let seen = self.released.generation();
+                if tracker.revisits.load(Ordering::SeqCst) != 0 {
+                    self.released.park(seen);
+                }
+                continue;
+            }
+
+            // Appends only take the tail lock.
+            let last = chunk == slices.len() - 1;
+            let _tail = last.then(|| self.tail.lock());
+            let chunk_len = if last { self.last_len(tracker) } else { tracker.length() };
+            // Edits to earlier chunks may have moved this one along
+            // since we picked it.
+            let at = index.wrapping_sub(tracker.start());
+            if at > chunk_len || (at == chunk_len && !inserting) {
+                continue;
+            }
+
+            board.locked(chunk);
+            tracker.edits.fetch_add(1, Ordering::SeqCst);
+            tracker.writing();
+            // SAFETY: We hold the chunk's lock, and the tail lock if
+            // it's the last one, and no iterator still wants part of it.
+            let segment = unsafe { &mut *data.segment(chunk) };
+            // SAFETY: Everything up to the tracker's length has been
+            // written, including anything appended to the last chunk.
+            unsafe { segment.set_len(chunk_len) };
+            let result = edit(segment, at);
+            tracker.data.store(segment.as_mut_ptr(), Ordering::Relaxed);
+            tracker.length.store(segment.len(), Ordering::Release);
+            if last {
+                self.settled.store(self.appended.load(Ordering::Relaxed), Ordering::Release);
+            }
+
+            for later in &slices[chunk + 1..] {
+                if inserting {
+                    later.start.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    later.start.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+            if inserting {
+                self.len.fetch_add(1, Ordering::Release);
+            } else {
+                self.len.fetch_sub(1, Ordering::Release);
+            }
+            tracker.edits.fetch_add(1, Ordering::SeqCst);
+            board.unlocked(chunk);
+
+            break (result, chunk, segment.len());
+        };
+
+        drop(guard);
+        if chunk_len > bounds.split_above || (chunk_len < bounds.merge_below && slices.len() > 1) {
+            self.try_resegment(chunk);
+        }
+        result
+    }
+
+    /// Splits or merges `chunk` if it's out of bounds, unless that
+    /// would mean waiting for the exclusive lock, since that's what
+    /// edits are meant to get around.
+    fn try_resegment(&self, chunk: usize) {
+        if let Some(guard) = LockGuard::try_lock(&self.all_lock, LockGuardType::Write) {
+            // SAFETY: `guard` is exclusive.
+            let bounds = unsafe { *self.bounds.get() };
+            unsafe { self.settled(&guard) }.resegment(chunk, bounds);
+            self.reconstruct_chunks(&guard, self.current_chunks());
+        }
+    }
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> RawCandyCane<R, M, T, SLICES, S> {
+    /// Whether an adaptive cane's chunks have been taken often
+    /// enough to decide on again. `all_lock` must be held.
+    fn rebalance_due(&self) -> bool {
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        let slices = unsafe { &*self.slices.get() };
+        match *self.adaptive.lock() {
+            Some(adaptive) => adaptive.due(slices.iter().filter_map(|x| x.window.as_ref())),
+            None => false,
+        }
+    }
+
+    /// Has whoever next takes the shared lock re-partition an
+    /// adaptive cane, if its chunks have been taken often enough.
+    /// `all_lock` must be held.
+    pub(crate) fn request_rebalance(&self) {
+        if self.rebalance_due() {
+            self.rebalance_pending.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Re-partitions an adaptive cane like dropping a write guard
+    /// would, unless that would mean waiting for the exclusive
+    /// lock, which anyone still holding the cane keeps us from.
+    fn try_rebalance(&self) {
+        if let Some(guard) = LockGuard::try_lock(&self.all_lock, LockGuardType::Write) {
+            self.rebalance_pending.store(false, Ordering::Relaxed);
+            // SAFETY: `guard` is exclusive.
+            let len = unsafe { self.settled(&guard) }.len();
+            let chunks = self.chunks_wanted(len);
+            if !self.chunks_kept(chunks) {
+                self.reconstruct_chunks(&guard, chunks);
+            }
+        }
+    }
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Default for RawCandyCane<R, M, T, SLICES, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Drop for RawCandyCane<R, M, T, SLICES, S> {
+    fn drop(&mut self) {
+        // So that appended elements are dropped along with the vec.
+        self.settled_mut();
+    }
+}
+
+// SAFETY: Sharing the cane hands out `&mut T` (through `write()`
+// and `iter_streaming_mut`) and `&T` (through `iter_streaming`)
+// on whichever thread asks for them, so `T` must be both.
+unsafe impl<R: RawRwLock + Sync, M: RawMutex + Sync, T: Send + Sync, const SLICES: usize, S: Storage> Sync for RawCandyCane<R, M, T, SLICES, S> {}
+// SAFETY: Moving the cane moves every `T` along with it.
+unsafe impl<R: RawRwLock + Send, M: RawMutex + Send, T: Send, const SLICES: usize, S: Storage> Send for RawCandyCane<R, M, T, SLICES, S> {}
+
+pub struct CandyCaneWriteGuard<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage = Contiguous> {
+    _hold: Timer<'a>,
     lock: LockGuard<'a, R>,
-    original: &'a RawCandyCane<R, M, T, SLICES>,
+    original: &'a RawCandyCane<R, M, T, SLICES, S>,
     vec: Vec<T>,
+    /// The `shape` of `vec` when the guard was made, to tell on
+    /// drop which chunks have to change.
+    before: (usize, usize, usize),
     _phantom: PhantomData<&'a mut Vec<UnsafeCell<T>>>
 }
 
-impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize> Deref for CandyCaneWriteGuard<'a, R, M, T, SLICES> {
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Deref for CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
     type Target = Vec<T>;
 
     fn deref(&self) -> &Self::Target {
@@ -281,244 +1133,271 @@ impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize> Deref for CandyCaneW
     }
 }
 
-impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize> DerefMut for CandyCaneWriteGuard<'a, R, M, T, SLICES> {
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> DerefMut for CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.vec
     }
 }
 
-impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize> Drop for CandyCaneWriteGuard<'a, R, M, T, SLICES> {
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Drop for CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
     fn drop(&mut self) {
-        let reconstructed_vec = unsafe {
-            let ptr = self.vec.as_mut_ptr().cast::<UnsafeCell<T>>();
-            let len = self.vec.len();
-            let cap = self.vec.capacity();
-
-            Vec::from_raw_parts(ptr, len, cap)
-        };
-
-        std::mem::forget(std::mem::take(&mut self.vec));
+        let after = shape(&self.vec);
+        let reconstructed_vec = into_cells(std::mem::take(&mut self.vec));
+        let chunks = self.original.chunks_wanted(reconstructed_vec.len());
 
         self.original.ensure_my_write_guard(&self.lock);
         unsafe {
-            *self.original.data.get() = reconstructed_vec;
+            *self.original.data.get() = S::Buffer::from_vec(reconstructed_vec, chunks);
+        }
+
+        // Changing only values leaves every chunk where it was, as
+        // long as the vec went back in as it is.
+        let moved = !S::Buffer::<T>::KEEPS_VEC || after.0 != self.before.0 || after.1 != self.before.1;
+        if !moved && after.2 == self.before.2 && self.original.chunks_kept(chunks) {
+            return;
         }
 
-        self.original.reconstruct_chunks(&self.lock);
+        self.original.rebuild_chunks(&self.lock, chunks, moved);
     }
 }
 
-// #[cfg(test)]
-// mod unit_tests {
-//     use crate::RawCandyCane;
-//     use hushed_panic::hush_this_test;
-//     use parking_lot::RawRwLock;
-//     use parking_lot::lock_api::RawRwLock as RRwlock;
-//     use std::sync::Arc;
-
-//     #[test]
-//     fn new() {
-//         RawCandyCane::<RawRwLock, (), 1>::new();
-//         RawCandyCane::<RawRwLock, u8, 1>::new();
-//         RawCandyCane::<RawRwLock, (), 100>::new();
-//         RawCandyCane::<RawRwLock, u8, 100>::new();
-//     }
-
-//     #[test]
-//     fn from_vec() {
-//         let unit_vec = vec![(); 90];
-//         let u8_vec = vec![0u8; 90];
-//         RawCandyCane::<RawRwLock, (), 1>::from_vec(unit_vec.clone());
-//         RawCandyCane::<RawRwLock, u8, 1>::from_vec(u8_vec.clone());
-//         RawCandyCane::<RawRwLock, (), 100>::from_vec(unit_vec);
-//         RawCandyCane::<RawRwLock, u8, 100>::from_vec(u8_vec);
-//     }
-
-//     #[test]
-//     #[should_panic]
-//     fn zero_slices() {
-//         let _x = hush_this_test();
-//         RawCandyCane::<RawRwLock, (), 0>::new();
-//     }
-
-//     fn make_data() -> Vec<usize> {
-//         (0..4000).collect()
-//     }
-
-//     fn iter_and_add<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, usize, SLICES>) {
-//         let mut iter = candy_cane.iter_streaming(..);
-//         let mut sum = 0;
-//         let mut count = 0;
-//         while let Some(item) = iter.next() {
-//             sum += *item;
-//             count += 1;
-//         }
-
-//         assert_eq!(count, 4000);
-//         assert_eq!(sum, (3999 * 4000) / 2);
-
-//         let (sum, count) = candy_cane
-//             .iter(..)
-//             .fold((0, 0), |(sum, count), val| (sum + *val, count + 1));
-
-//         assert_eq!(count, 4000);
-//         assert_eq!(sum, (3999 * 4000) / 2);
-//     }
-
-//     fn assure_final_state<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, usize, SLICES>) {
-//         assert!(candy_cane.all_lock.try_lock_exclusive());
-//         unsafe {
-//             candy_cane.all_lock.unlock_exclusive();
-//         }
-//     }
-
-//     #[test]
-//     fn iterate_1_single_threaded() {
-//         let data = make_data();
-
-//         let candy_cane = RawCandyCane::<RawRwLock, _, 1>::from_vec(data);
-
-//         iter_and_add(&candy_cane);
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_3_single_threaded() {
-//         let data = make_data();
-
-//         let candy_cane = RawCandyCane::<RawRwLock, _, 3>::from_vec(data);
-
-//         iter_and_add(&candy_cane);
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_1_multi_threaded() {
-//         let data = make_data();
-
-//         let candy_cane = Arc::new(RawCandyCane::<RawRwLock, _, 1>::from_vec(data));
-
-//         let threads = (0..4)
-//             .map(|_| {
-//                 let clone = Arc::clone(&candy_cane);
-//                 std::thread::spawn(move || {
-//                     iter_and_add(&*clone);
-//                 })
-//             })
-//             .collect::<Vec<_>>();
-
-//         threads
-//             .into_iter()
-//             .for_each(|x| x.join().unwrap());
-
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_3_multi_threaded() {
-//         let data = make_data();
-
-//         let candy_cane = Arc::new(RawCandyCane::<RawRwLock, _, 3>::from_vec(data));
-
-//         let threads = (0..7)
-//             .map(|_| {
-//                 let clone = Arc::clone(&candy_cane);
-//                 std::thread::spawn(move || {
-//                     iter_and_add(&*clone);
-//                 })
-//             })
-//             .collect::<Vec<_>>();
-
-//         threads
-//             .into_iter()
-//             .for_each(|x| x.join().unwrap());
-
-//         assure_final_state(&candy_cane);
-//     }
-
-//     fn iter_and_add_mut<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, usize, SLICES>) {
-//         let mut iter = candy_cane.iter_streaming_mut(..);
-//         let mut sum = 0;
-//         let mut count = 0;
-//         while let Some(item) = iter.next() {
-//             sum += *item;
-//             count += 1;
-//         }
-
-//         assert_eq!(count, 4000);
-//         assert_eq!(sum, (3999 * 4000) / 2);
-
-//         let (sum, count) = candy_cane
-//             .iter_mut(..)
-//             .fold((0, 0), |(sum, count), val| (sum + *val, count + 1));
-
-//         assert_eq!(count, 4000);
-//         assert_eq!(sum, (3999 * 4000) / 2);
-//     }
-
-//     #[test]
-//     fn iterate_1_single_threaded_mut() {
-//         let data = make_data();
-
-//         let candy_cane = RawCandyCane::<RawRwLock, _, 1>::from_vec(data);
-
-//         iter_and_add_mut(&candy_cane);
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_3_single_threaded_mut() {
-//         let data = make_data();
-
-//         let candy_cane = RawCandyCane::<RawRwLock, _, 3>::from_vec(data);
-
-//         iter_and_add_mut(&candy_cane);
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_1_multi_threaded_mut() {
-//         let data = make_data();
-
-//         let candy_cane = Arc::new(RawCandyCane::<RawRwLock, _, 1>::from_vec(data));
-
-//         let threads = (0..4)
-//             .map(|_| {
-//                 let clone = Arc::clone(&candy_cane);
-//                 std::thread::spawn(move || {
-//                     iter_and_add_mut(&*clone);
-//                 })
-//             })
-//             .collect::<Vec<_>>();
-
-//         threads
-//             .into_iter()
-//             .for_each(|x| x.join().unwrap());
-
-//         assure_final_state(&candy_cane);
-//     }
-
-//     #[test]
-//     fn iterate_3_multi_threaded_mut() {
-//         let data = make_data();
-
-//         let candy_cane = Arc::new(RawCandyCane::<RawRwLock, _, 3>::from_vec(data));
-
-//         let threads = (0..7)
-//             .map(|_| {
-//                 let clone = Arc::clone(&candy_cane);
-//                 std::thread::spawn(move || {
-//                     // println!("{:?} Started", std::thread::current().id());
-//                     iter_and_add_mut(&*clone);
-//                     // println!("{:?} Ended", std::thread::current().id());
-//                 })
-//             })
-//             .collect::<Vec<_>>();
-
-//         threads
-//             .into_iter()
-//             .for_each(|x| x.join().unwrap());
-
-//         assure_final_state(&candy_cane);
-//     }
-// }
+#[cfg(test)]
+mod unit_tests {
+    use crate::RawCandyCane;
+    use hushed_panic::hush_this_test;
+    use parking_lot::{RawRwLock, RawMutex};
+    use parking_lot::lock_api::RawRwLock as RRwlock;
+    use std::sync::Arc;
+
+    #[test]
+    fn new() {
+        RawCandyCane::<RawRwLock, RawMutex, (), 1>::new();
+        RawCandyCane::<RawRwLock, RawMutex, u8, 1>::new();
+        RawCandyCane::<RawRwLock, RawMutex, (), 100>::new();
+        RawCandyCane::<RawRwLock, RawMutex, u8, 100>::new();
+    }
+
+    #[test]
+    fn from_vec() {
+        let unit_vec = vec![(); 90];
+        let u8_vec = vec![0u8; 90];
+        RawCandyCane::<RawRwLock, RawMutex, (), 1>::from_vec(unit_vec.clone());
+        RawCandyCane::<RawRwLock, RawMutex, u8, 1>::from_vec(u8_vec.clone());
+        RawCandyCane::<RawRwLock, RawMutex, (), 100>::from_vec(unit_vec);
+        RawCandyCane::<RawRwLock, RawMutex, u8, 100>::from_vec(u8_vec);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_slices() {
+        let _x = hush_this_test();
+        RawCandyCane::<RawRwLock, RawMutex, (), 0>::new();
+    }
+
+    /// Miri is far too slow to iterate over thousands of elements.
+    const DATA_LEN: usize = if cfg!(miri) { 200 } else { 4000 };
+
+    fn make_data() -> Vec<usize> {
+        (0..DATA_LEN).collect()
+    }
+
+    fn iter_and_add<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, RawMutex, usize, SLICES>) {
+        let mut iter = candy_cane.iter_streaming(..);
+        let mut sum = 0;
+        let mut count = 0;
+        while let Some(item) = iter.next() {
+            sum += *item;
+            count += 1;
+        }
+
+        assert_eq!(count, DATA_LEN);
+        assert_eq!(sum, ((DATA_LEN - 1) * DATA_LEN) / 2);
+    }
+
+    fn iter_and_add_mut<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, RawMutex, usize, SLICES>) {
+        let mut iter = candy_cane.iter_streaming_mut(..);
+        let mut sum = 0;
+        let mut count = 0;
+        while let Some(item) = iter.next() {
+            sum += *item;
+            count += 1;
+        }
+
+        assert_eq!(count, DATA_LEN);
+        assert_eq!(sum, ((DATA_LEN - 1) * DATA_LEN) / 2);
+    }
+
+    fn assure_final_state<R: RRwlock, const SLICES: usize>(candy_cane: &RawCandyCane<R, RawMutex, usize, SLICES>) {
+        assert!(candy_cane.all_lock.try_lock_exclusive());
+        unsafe {
+            candy_cane.all_lock.unlock_exclusive();
+        }
+    }
+
+    fn multi_threaded<const SLICES: usize>(threads: usize, f: fn(&RawCandyCane<RawRwLock, RawMutex, usize, SLICES>)) {
+        let candy_cane = Arc::new(RawCandyCane::<RawRwLock, RawMutex, _, SLICES>::from_vec(make_data()));
+
+        let threads = (0..threads)
+            .map(|_| {
+                let clone = Arc::clone(&candy_cane);
+                std::thread::spawn(move || f(&*clone))
+            })
+            .collect::<Vec<_>>();
+
+        threads
+            .into_iter()
+            .for_each(|x| x.join().unwrap());
+
+        assure_final_state(&candy_cane);
+    }
+
+    #[test]
+    fn iterate_1_single_threaded() {
+        let candy_cane = RawCandyCane::<RawRwLock, RawMutex, _, 1>::from_vec(make_data());
+
+        iter_and_add(&candy_cane);
+        assure_final_state(&candy_cane);
+    }
+
+    #[test]
+    fn iterate_3_single_threaded() {
+        let candy_cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(make_data());
+
+        iter_and_add(&candy_cane);
+        assure_final_state(&candy_cane);
+    }
+
+    #[test]
+    fn iterate_1_multi_threaded() {
+        multi_threaded::<1>(4, iter_and_add);
+    }
+
+    #[test]
+    fn iterate_3_multi_threaded() {
+        multi_threaded::<3>(7, iter_and_add);
+    }
+
+    #[test]
+    fn iterate_1_single_threaded_mut() {
+        let candy_cane = RawCandyCane::<RawRwLock, RawMutex, _, 1>::from_vec(make_data());
+
+        iter_and_add_mut(&candy_cane);
+        assure_final_state(&candy_cane);
+    }
+
+    #[test]
+    fn iterate_3_single_threaded_mut() {
+        let candy_cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(make_data());
+
+        iter_and_add_mut(&candy_cane);
+        assure_final_state(&candy_cane);
+    }
+
+    #[test]
+    fn iterate_1_multi_threaded_mut() {
+        multi_threaded::<1>(4, iter_and_add_mut);
+    }
+
+    #[test]
+    fn iterate_3_multi_threaded_mut() {
+        multi_threaded::<3>(7, iter_and_add_mut);
+    }
+
+    #[test]
+    #[cfg(not(feature = "stats"))]
+    fn stats_compile_to_nothing() {
+        use crate::stats::{CaneCounters, ChunkCounters, Timer};
+        use std::mem::{needs_drop, size_of};
+
+        assert_eq!(size_of::<CaneCounters>(), 0);
+        assert_eq!(size_of::<ChunkCounters>(), 0);
+        assert_eq!(size_of::<Timer>(), 0);
+        assert!(!needs_drop::<Timer>());
+    }
+
+    #[test]
+    fn exclusive_access() {
+        let mut cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(make_data());
+        cane.iter_mut_exclusive().for_each(|x| *x *= 2);
+        cane.as_mut_slice()[1] += 1;
+
+        let mut iter = cane.iter_streaming(..2);
+        assert_eq!(iter.next_slice(), Some(&[0, 3][..]));
+        assert_eq!(iter.next_slice(), None);
+        drop(iter);
+        assure_final_state(&cane);
+    }
+
+    fn chunks<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, usize, SLICES>) -> Vec<(usize, usize, usize)> {
+        let slices = unsafe { &*cane.slices.get() };
+        slices.iter().map(|x| (x.data() as usize, x.start(), x.length())).collect()
+    }
+
+    #[test]
+    fn writing_values_keeps_the_chunks() {
+        let cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(make_data());
+        let before = chunks(&cane);
+
+        cane.write().iter_mut().for_each(|x| *x *= 2);
+        assert_eq!(chunks(&cane), before);
+
+        let mut iter = cane.iter_streaming(..);
+        let mut sum = 0;
+        while let Some(item) = iter.next() {
+            sum += *item;
+        }
+        assert_eq!(sum, (DATA_LEN - 1) * DATA_LEN);
+    }
+
+    #[test]
+    fn growing_the_tail_only_moves_the_last_chunk() {
+        let mut data = make_data();
+        data.reserve(1);
+        let cane = RawCandyCane::<RawRwLock, RawMutex, _, 4>::from_vec(data);
+        let before = chunks(&cane);
+
+        cane.write().push(DATA_LEN);
+        let after = chunks(&cane);
+        assert_eq!(after[..3], before[..3]);
+        assert_eq!(after[3], (before[3].0, before[3].1, before[3].2 + 1));
+
+        let mut iter = cane.iter_streaming(..);
+        let mut sum = 0;
+        while let Some(item) = iter.next() {
+            sum += *item;
+        }
+        assert_eq!(sum, DATA_LEN * (DATA_LEN + 1) / 2);
+    }
+
+    #[test]
+    fn moved_vecs_move_the_chunks() {
+        let cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(make_data());
+        let before = chunks(&cane);
+
+        let mut guard = cane.write();
+        *guard = guard.clone();
+        drop(guard);
+        let after = chunks(&cane);
+        for (after, before) in after.iter().zip(&before) {
+            assert_ne!(after.0, before.0);
+            assert_eq!((after.1, after.2), (before.1, before.2));
+        }
+
+        iter_and_add(&cane);
+        assure_final_state(&cane);
+    }
+
+    #[test]
+    fn write_then_read() {
+        let candy_cane = RawCandyCane::<RawRwLock, RawMutex, _, 3>::from_vec(vec![Box::new(1)]);
+        candy_cane.write().push(Box::new(2));
+
+        let mut iter = candy_cane.iter_streaming(..);
+        let mut sum = 0;
+        while let Some(item) = iter.next() {
+            sum += **item;
+        }
+
+        assert_eq!(sum, 3);
+    }
+}
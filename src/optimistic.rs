@@ -0,0 +1,128 @@
+//! Reading small `Copy` elements without locking their chunk.
+//!
+//! Anyone writing to a chunk's elements under the shared lock marks
+//! its sequence number odd first, and whoever holds the chunk's lock
+//! makes it even again when they let go of it. A reader copies the
+//! elements out while the number is even, and tries again if it has
+//! changed by the time it's done. A number that stays odd, or keeps
+//! changing, means a writer holds on to the chunk, so after a few
+//! tries the reader locks the chunk instead, and waits for them to
+//! let go of it like an iterator would. Until then, writers don't
+//! wait for readers.
+//!
+//! A copy taken while the elements were being written is thrown away
+//! without being looked at, but taking it is still a data race by the
+//! letter of the memory model, as it is for every seqlock. So it's read
+//! volatile, and kept as `MaybeUninit` until it's known to be whole.
+//!
+//! Only contiguous canes are read this way, since a segmented cane's
+//! chunk can be moved to a new allocation under the shared lock, and
+//! the old one freed while it's being read.
+
+use crate::storage::Contiguous;
+use crate::RawCandyCane;
+use crate::deadlock;
+use crate::wait::Backoff;
+use parking_lot::lock_api::{RawMutex, RawRwLock};
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::sync::atomic::{fence, Ordering};
+
+/// How often a copy is taken before locking the chunk instead.
+const TRIES: usize = 8;
+
+impl<R: RawRwLock, M: RawMutex, T: Copy + Send, const SLICES: usize> RawCandyCane<R, M, T, SLICES, Contiguous> {
+    /// A copy of the element at `index`, or `None` if there isn't
+    /// one, taken without locking its chunk.
+    ///
+    /// Like an iterator, this waits for writers, but unlike one, it
+    /// doesn't wait for anyone holding the chunk, unless they're
+    /// writing to it. Then it waits until they let go of the chunk,
+    /// which for a mutable iterator is once it's done with all of
+    /// it, and holds up other writers of the chunk until it's done.
+    ///
+    /// # Panics
+    ///
+    /// If the chunk is being written to while this thread holds it,
+    /// or part of it, through an iterator or `write_range` guard
+    /// that's still alive, since this would never return. Copy the
+    /// element out of the iterator or guard instead.
+    ///
+    /// In debug builds, also if this thread holds a write guard.
+    pub fn get_copy(&self, index: usize) -> Option<T> {
+        let _guard = self.lock_internal_for_read();
+        if index >= self.len.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: `_guard` is shared, so the vec can't be reallocated,
+        // and everything up to `len` has been written, even past
+        // `data.len()`.
+        let element = unsafe { (*self.data.get()).as_ptr().add(index).cast::<MaybeUninit<T>>() };
+        // SAFETY: As above.
+        let copy = self.read_optimistic(self.calc_slice_index(index), || unsafe { element.read_volatile() });
+        // SAFETY: Nothing wrote to it while it was copied.
+        Some(unsafe { copy.assume_init() })
+    }
+
+    /// A copy of every element in `chunk`, or `None` if there's no
+    /// such chunk, taken like [`get_copy`](Self::get_copy) does.
+    ///
+    /// # Panics
+    ///
+    /// Like `get_copy`.
+    pub fn read_chunk_copy(&self, chunk: usize) -> Option<Vec<T>> {
+        let _guard = self.lock_internal_for_read();
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        let slices = unsafe { &*self.slices.get() };
+        let tracker = slices.get(chunk)?;
+
+        let start = tracker.start();
+        // The last chunk also holds everything appended since.
+        let end = if chunk == slices.len() - 1 { self.len.load(Ordering::Acquire) } else { start + tracker.length() };
+        // SAFETY: As in `get_copy`.
+        let elements = unsafe { (*self.data.get()).as_ptr().add(start).cast::<MaybeUninit<T>>() };
+
+        let mut copy = Vec::with_capacity(end - start);
+        self.read_optimistic(chunk, || {
+            copy.clear();
+            // SAFETY: As above.
+            copy.extend((0..end - start).map(|index| unsafe { elements.add(index).read_volatile() }));
+        });
+
+        let mut copy = ManuallyDrop::new(copy);
+        // SAFETY: Nothing wrote to them while they were copied, and
+        // `MaybeUninit<T>` has the same layout as `T`.
+        Some(unsafe { Vec::from_raw_parts(copy.as_mut_ptr().cast(), copy.len(), copy.capacity()) })
+    }
+
+    /// Runs `copy` until nothing was written to `chunk` while it
+    /// ran, or under the chunk's lock once that's taken too many
+    /// tries. The shared lock must be held.
+    fn read_optimistic<U>(&self, chunk: usize, mut copy: impl FnMut() -> U) -> U {
+        // SAFETY: `slices` is only changed under the exclusive lock.
+        let slices = unsafe { &*self.slices.get() };
+        let tracker = &slices[chunk];
+        let mut backoff = Backoff::default();
+        for _ in 0..TRIES {
+            let before = tracker.seq.load(Ordering::Acquire);
+            if before % 2 == 0 {
+                let copied = copy();
+                // Keeps the reads in `copy` from being seen after this.
+                fence(Ordering::Acquire);
+                if tracker.seq.load(Ordering::Relaxed) == before {
+                    return copied;
+                }
+            }
+            backoff.snooze();
+        }
+
+        // It stays odd for as long as a mutable iterator holds the
+        // chunk, so rather than spin until then, wait for the lock
+        // like an iterator would. Nobody writes to the chunk while
+        // it's held by someone who isn't.
+        let cane = deadlock::id_of(&self.all_lock);
+        deadlock::check_chunk_holder(cane, chunk, tracker.holder.load(Ordering::Relaxed));
+        let _chunk = tracker.lock(cane, chunk, &self.released);
+        copy()
+    }
+}
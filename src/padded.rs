@@ -0,0 +1,25 @@
+//! Keeps hot lock state off of its neighbours' cache lines.
+
+use std::ops::{Deref, DerefMut};
+
+/// Aligns `T` to 128 bytes. Lines are 64 bytes on most
+/// machines, but x86 prefetches them in pairs and recent
+/// ARM cores use 128 byte lines, so 64 isn't enough to
+/// keep two values from contending.
+#[derive(Default)]
+#[repr(align(128))]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
@@ -0,0 +1,108 @@
+//! Where an iterator looks first for a free chunk.
+//!
+//! Iterators started together all want the same chunks, so if
+//! they all looked in the same place first they would queue up on
+//! one chunk while the others sat idle. A policy spreads them out.
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How an iterator searches for a free chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scan {
+    /// Upwards from this chunk, wrapping around to the first
+    /// one. The search carries on from wherever the iterator
+    /// took its last chunk.
+    Up(usize),
+    /// Downwards from the last chunk, every time.
+    Down,
+}
+
+/// Picks where an iterator starts looking for a free chunk,
+/// set with [`RawCandyCane::set_chunk_policy`].
+///
+/// [`RawCandyCane::set_chunk_policy`]: crate::RawCandyCane::set_chunk_policy
+pub trait ChunkPolicy: Send + Sync + 'static {
+    /// Called once per iterator, with the chunks it covers, and
+    /// again for each range an interruptible one plans anew.
+    /// Any chunk outside of `chunks` starts from the first.
+    fn scan(&self, chunks: Range<usize>) -> Scan;
+
+    /// Called whenever an iterator takes `chunk`'s lock.
+    fn took(&self, _chunk: usize) {}
+}
+
+/// Always tries the last chunk first, and works backwards.
+/// This is the default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ReverseScan;
+
+impl ChunkPolicy for ReverseScan {
+    fn scan(&self, _chunks: Range<usize>) -> Scan {
+        Scan::Down
+    }
+}
+
+/// Starts each iterator one chunk further along than the
+/// last one.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl ChunkPolicy for RoundRobin {
+    fn scan(&self, chunks: Range<usize>) -> Scan {
+        let offset = self.next.fetch_add(1, Ordering::Relaxed) % chunks.len().max(1);
+        Scan::Up(chunks.start + offset)
+    }
+}
+
+/// Starts from a chunk picked by hashing the thread's id, so
+/// that a thread always starts from the same place.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ThreadHashed;
+
+impl ChunkPolicy for ThreadHashed {
+    fn scan(&self, chunks: Range<usize>) -> Scan {
+        let mut hasher = DefaultHasher::new();
+        std::thread::current().id().hash(&mut hasher);
+        let offset = hasher.finish() % chunks.len().max(1) as u64;
+        Scan::Up(chunks.start + offset as usize)
+    }
+}
+
+thread_local! {
+    /// The policy and chunk of the last chunk this thread took.
+    static LAST_TAKEN: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Starts from the chunk this thread took last, which is the
+/// one most likely to still be in its cache.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Sticky {
+    /// Not zero sized, so that every `Sticky` has its own
+    /// address to tell its chunks apart from those of others.
+    _id: u8,
+}
+
+impl Sticky {
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl ChunkPolicy for Sticky {
+    fn scan(&self, chunks: Range<usize>) -> Scan {
+        match LAST_TAKEN.with(Cell::get) {
+            Some((policy, chunk)) if policy == self.id() => Scan::Up(chunk),
+            _ => Scan::Up(chunks.start),
+        }
+    }
+
+    fn took(&self, chunk: usize) {
+        LAST_TAKEN.with(|last| last.set(Some((self.id(), chunk))));
+    }
+}
@@ -0,0 +1,85 @@
+//! Writing to part of a cane without stopping every reader.
+//!
+//! `write()` takes the exclusive lock, which waits for every
+//! iterator and keeps new ones from starting. A range guard only
+//! takes the shared lock, like an iterator does, and then locks the
+//! chunks its range covers. Iterators over other chunks carry on,
+//! and those wanting one of its chunks visit the others first.
+//!
+//! Chunks are always locked in ascending order, and an iterator never
+//! blocks on a chunk while holding another, so two range guards, or a
+//! range guard and an iterator, can't deadlock each other.
+
+use crate::claim::Board;
+use crate::slice_tracker::{ChunkGuard, LockGuard};
+use parking_lot::lock_api::{RawMutex, RawRwLock};
+use parking_lot::RawRwLock as RwLock;
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut, Range};
+
+/// Returned by [`RawCandyCane::write_range`], and derefs to
+/// the elements in that range.
+///
+/// [`RawCandyCane::write_range`]: crate::RawCandyCane::write_range
+pub struct CandyCaneRangeGuard<'a, T, R: RawRwLock = RwLock, M: RawMutex = parking_lot::RawMutex> {
+    data: &'a [UnsafeCell<T>],
+    board: &'a Board,
+    /// The ids of the chunks in `chunks`.
+    ids: Range<usize>,
+    chunks: Vec<ChunkGuard<'a, M>>,
+    /// Must outlive the chunk guards, so comes after them.
+    _all_lock: LockGuard<'a, R>,
+}
+
+impl<'a, T, R: RawRwLock, M: RawMutex> CandyCaneRangeGuard<'a, T, R, M> {
+    /// `chunks` must hold the locks of every chunk in `ids`,
+    /// which must cover all of `data`, and `all_lock` must be
+    /// the shared lock they were taken under.
+    pub(crate) fn new(
+        data: &'a [UnsafeCell<T>],
+        board: &'a Board,
+        ids: Range<usize>,
+        chunks: Vec<ChunkGuard<'a, M>>,
+        all_lock: LockGuard<'a, R>,
+    ) -> Self {
+        ids.clone().for_each(|chunk| board.locked(chunk));
+
+        Self {
+            data,
+            board,
+            ids,
+            chunks,
+            _all_lock: all_lock,
+        }
+    }
+
+    /// How many chunks are locked by this guard.
+    pub fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+impl<'a, T, R: RawRwLock, M: RawMutex> Deref for CandyCaneRangeGuard<'a, T, R, M> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: We hold the locks of every chunk `data` is in, and
+        // `UnsafeCell<T>` has the same layout as `T`.
+        unsafe { &*(self.data as *const [UnsafeCell<T>] as *const [T]) }
+    }
+}
+
+impl<'a, T, R: RawRwLock, M: RawMutex> DerefMut for CandyCaneRangeGuard<'a, T, R, M> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: As above, and nobody else can be looking at
+        // any of it while we hold those locks.
+        unsafe { std::slice::from_raw_parts_mut(UnsafeCell::raw_get(self.data.as_ptr()), self.data.len()) }
+    }
+}
+
+impl<'a, T, R: RawRwLock, M: RawMutex> Drop for CandyCaneRangeGuard<'a, T, R, M> {
+    fn drop(&mut self) {
+        // The chunk guards are dropped after this.
+        self.ids.clone().for_each(|chunk| self.board.unlocked(chunk));
+    }
+}
@@ -1,2 +1,7 @@
 pub use crate::iter::streaming::RawCandyCaneIterStreaming;
 pub use crate::RawCandyCane;
+
+/// Only public for the claiming benchmarks, which compare them
+/// with trying every chunk's lock. Not part of the API.
+#[doc(hidden)]
+pub use crate::claim::{Board, Wanted};
@@ -0,0 +1,462 @@
+//! A cane whose readers never wait, read-copy-update style.
+//!
+//! Readers pin the epoch they started in, and look at whichever
+//! version of the elements was published last. Nothing ever changes
+//! a published version. A writer builds its own out of the last one,
+//! and publishes it by swapping a single pointer, so readers which
+//! started before keep seeing theirs, and those which start after
+//! see the new one.
+//!
+//! A version is a list of chunks, each behind an `Arc`. Starting a
+//! new version only copies that list, and a chunk is cloned the first
+//! time the writer changes it, so a write costs about as much as the
+//! chunks it touched.
+//!
+//! The version a writer replaced is retired along with the epoch it
+//! was replaced in, and freed once no reader is pinned to that epoch
+//! or an earlier one. Only writers free anything, so a retired version
+//! outlives its last reader until the next write.
+
+use crate::deadlock::{self, Held};
+use crate::padded::CachePadded;
+use parking_lot::{Mutex, MutexGuard};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// What a reader's slot holds while nobody is pinned to it.
+/// Epochs start after it.
+const UNPINNED: usize = 0;
+
+/// Chunks are at least this long, so that pushing onto a small
+/// cane doesn't start a new chunk every time.
+const MIN_CHUNK: usize = 64;
+
+/// One published state of the elements. Every chunk but the last
+/// is `chunk_len` long, and none are empty.
+struct Version<T> {
+    chunks: Vec<Arc<Vec<T>>>,
+    chunk_len: usize,
+    len: usize,
+}
+
+impl<T> Version<T> {
+    /// Which chunk `index` is in, and where in it.
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        (index < self.len).then(|| (index / self.chunk_len, index % self.chunk_len))
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        let (chunk, at) = self.locate(index)?;
+        Some(&self.chunks[chunk][at])
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = &[T]> {
+        self.chunks.iter().map(|chunk| chunk.as_slice())
+    }
+}
+
+// Not derived, since sharing the chunks doesn't need `T: Clone`.
+impl<T> Clone for Version<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+            chunk_len: self.chunk_len,
+            len: self.len,
+        }
+    }
+}
+
+/// Where a reader says which epoch it's pinned to. Slots are
+/// never unlinked while the cane is alive, and each reader takes
+/// whichever one is free.
+struct Slot {
+    pinned: AtomicUsize,
+    /// Never changed once the slot is linked.
+    next: *mut CachePadded<Slot>,
+}
+
+/// SAFETY: `version` must have come from `Box::into_raw`, and no
+/// reader may still be looking at it.
+unsafe fn free<T>(version: *mut Version<T>) {
+    drop(Box::from_raw(version));
+}
+
+/// A list of elements split into chunks, which any number of
+/// threads can read without ever waiting, even while it's being
+/// written.
+///
+/// [`read`](Self::read) hands out the version of the elements
+/// published last, and [`write`](Self::write) a copy of it which
+/// is published once its guard is dropped. Only the chunks a
+/// writer changes are cloned, which is why writing needs
+/// `T: Clone`. Writers still wait for each other.
+///
+/// The elements start out in `SLICES` chunks, or fewer if those
+/// would be shorter than 64 elements. Pushing past the last one
+/// starts another chunk of the same length.
+///
+/// ```
+/// # use candy_cane::RcuCandyCane;
+/// let cane = RcuCandyCane::<u32>::from_vec(vec![1, 2, 3]);
+///
+/// let before = cane.read();
+/// cane.write().push(4);
+/// assert_eq!(before.len(), 3);
+/// assert_eq!(cane.read().len(), 4);
+/// ```
+pub struct RcuCandyCane<T, const SLICES: usize = 6> {
+    /// The version published last, from `Box::into_raw`.
+    current: AtomicPtr<Version<T>>,
+    /// Bumped every time a version is replaced.
+    epoch: AtomicUsize,
+    /// Every reader's slot, newest first.
+    slots: AtomicPtr<CachePadded<Slot>>,
+    /// Held by the one writer. The versions it replaced, oldest
+    /// first, each with the epoch it was replaced in.
+    retired: Mutex<Vec<(usize, *mut Version<T>)>>,
+}
+
+// SAFETY: The versions and slots behind the pointers belong to the
+// cane. Writers clone and drop elements on whichever thread they
+// run on, while readers on any other thread share them.
+unsafe impl<T: Send, const SLICES: usize> Send for RcuCandyCane<T, SLICES> {}
+unsafe impl<T: Send + Sync, const SLICES: usize> Sync for RcuCandyCane<T, SLICES> {}
+
+impl<T, const SLICES: usize> RcuCandyCane<T, SLICES> {
+    pub fn new() -> Self {
+        Self::from_vec(Vec::new())
+    }
+
+    pub fn from_vec(mut data: Vec<T>) -> Self {
+        assert_ne!(SLICES, 0);
+
+        let len = data.len();
+        let chunk_len = len.div_ceil(SLICES).max(MIN_CHUNK);
+        // Split from the back, so that each element is moved once.
+        let mut chunks = (1..len.div_ceil(chunk_len)).rev().map(|chunk| Arc::new(data.split_off(chunk * chunk_len))).collect::<Vec<_>>();
+        if !data.is_empty() {
+            chunks.push(Arc::new(data));
+        }
+        chunks.reverse();
+
+        let version = Version { chunks, chunk_len, len };
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(version))),
+            epoch: AtomicUsize::new(UNPINNED + 1),
+            slots: AtomicPtr::new(ptr::null_mut()),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// How many elements the version published last has.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The version of the elements published last. Never waits,
+    /// not even for a writer, whose changes it won't see.
+    ///
+    /// It can't be freed while the guard is alive, and neither can
+    /// any version published after it, so holding onto it for long
+    /// keeps every write since in memory.
+    pub fn read(&self) -> RcuReadGuard<'_, T> {
+        let slot = self.pin(self.epoch.load(Ordering::SeqCst));
+        // SAFETY: Loaded after pinning, so `reclaim` won't free it
+        // until the slot is unpinned.
+        let version = unsafe { &*self.current.load(Ordering::SeqCst) };
+        RcuReadGuard { version, pinned: &slot.pinned }
+    }
+
+    /// Pins a free slot to `epoch`, adding one if there's none.
+    fn pin(&self, epoch: usize) -> &Slot {
+        let head = self.slots.load(Ordering::SeqCst);
+
+        let mut next = head;
+        // SAFETY: Slots are only freed along with the cane.
+        while let Some(slot) = unsafe { next.as_ref() } {
+            let pinned = &slot.pinned;
+            if pinned.load(Ordering::Relaxed) == UNPINNED && pinned.compare_exchange(UNPINNED, epoch, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                return slot;
+            }
+            next = slot.next;
+        }
+
+        let slot = Box::into_raw(Box::new(CachePadded(Slot {
+            pinned: AtomicUsize::new(epoch),
+            next: head,
+        })));
+        let mut head = head;
+        while let Err(newer) = self.slots.compare_exchange(head, slot, Ordering::SeqCst, Ordering::SeqCst) {
+            head = newer;
+            // SAFETY: Nobody else can see it until it's linked.
+            unsafe { (*slot).0.next = newer };
+        }
+        // SAFETY: As above.
+        unsafe { &*slot }
+    }
+
+    /// Frees every retired version which no reader can be looking at.
+    fn reclaim(&self, retired: &mut Vec<(usize, *mut Version<T>)>) {
+        if retired.is_empty() {
+            return;
+        }
+
+        // A reader which loaded a version before it was replaced
+        // loaded the epoch before that too, so it's pinned to the
+        // epoch the version was replaced in, or an earlier one.
+        let mut oldest = usize::MAX;
+        let mut next = self.slots.load(Ordering::SeqCst);
+        // SAFETY: Slots are only freed along with the cane.
+        while let Some(slot) = unsafe { next.as_ref() } {
+            match slot.pinned.load(Ordering::SeqCst) {
+                UNPINNED => {}
+                epoch => oldest = oldest.min(epoch),
+            }
+            next = slot.next;
+        }
+
+        retired.retain(|&(epoch, version)| {
+            let unread = epoch < oldest;
+            if unread {
+                // SAFETY: See above. Every reader which could have
+                // loaded it has since unpinned, with `Release`.
+                unsafe { free(version) };
+            }
+            !unread
+        });
+    }
+
+    /// What the deadlock registry knows the cane by.
+    fn id(&self) -> usize {
+        deadlock::id_of(&self.retired)
+    }
+
+    pub fn into_inner(mut self) -> Vec<T> {
+        // SAFETY: Nobody can be reading anything anymore.
+        self.retired.get_mut().drain(..).for_each(|(_, version)| unsafe { free(version) });
+        let current = mem::replace(self.current.get_mut(), ptr::null_mut());
+        // SAFETY: As above. `Drop` skips it once it's null.
+        let version = unsafe { Box::from_raw(current) };
+
+        // Every other version is gone, so only this one has the chunks.
+        let mut data = Vec::with_capacity(version.len);
+        for chunk in version.chunks {
+            data.extend(Arc::into_inner(chunk).expect("chunk still shared"));
+        }
+        data
+    }
+}
+
+impl<T: Clone, const SLICES: usize> RcuCandyCane<T, SLICES> {
+    /// A copy of the version published last, which replaces it
+    /// once the guard is dropped, if anything was changed through
+    /// it. Waits for any other writer, but never for readers.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if this thread is already holding a write
+    /// guard for this cane, since this would never return.
+    pub fn write(&self) -> RcuWriteGuard<'_, T, SLICES> {
+        deadlock::check_write(self.id());
+        let retired = self.retired.lock();
+        deadlock::acquired(self.id(), Held::Exclusive);
+
+        // SAFETY: Only writers replace it, and nobody else is one.
+        let version = unsafe { (*self.current.load(Ordering::Acquire)).clone() };
+        RcuWriteGuard {
+            cane: self,
+            retired,
+            version,
+            changed: false,
+        }
+    }
+}
+
+impl<T, const SLICES: usize> Default for RcuCandyCane<T, SLICES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const SLICES: usize> Drop for RcuCandyCane<T, SLICES> {
+    fn drop(&mut self) {
+        // SAFETY: Nobody can be reading anything anymore.
+        self.retired.get_mut().drain(..).for_each(|(_, version)| unsafe { free(version) });
+        let current = *self.current.get_mut();
+        if !current.is_null() {
+            // SAFETY: As above.
+            unsafe { free(current) };
+        }
+
+        let mut next = *self.slots.get_mut();
+        while !next.is_null() {
+            // SAFETY: Every slot came from `Box::into_raw`, and
+            // nobody's pinned to any of them.
+            let slot = unsafe { Box::from_raw(next) };
+            next = slot.next;
+        }
+    }
+}
+
+/// Returned by [`RcuCandyCane::read`]. Keeps its version from
+/// being freed until it's dropped.
+pub struct RcuReadGuard<'a, T> {
+    version: &'a Version<T>,
+    pinned: &'a AtomicUsize,
+}
+
+impl<'a, T> RcuReadGuard<'a, T> {
+    pub fn len(&self) -> usize {
+        self.version.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.version.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks().flatten()
+    }
+
+    /// Each chunk's elements, in order, for splitting the
+    /// elements up between threads.
+    pub fn chunks(&self) -> impl Iterator<Item = &[T]> {
+        self.version.chunks()
+    }
+}
+
+impl<'a, T> Drop for RcuReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.pinned.store(UNPINNED, Ordering::Release);
+    }
+}
+
+/// Returned by [`RcuCandyCane::write`]. Starts out sharing every
+/// chunk with the version it was copied from, and clones each one
+/// the first time it's changed.
+///
+/// Dropping it publishes the copy, unless nothing was borrowed
+/// mutably from it, and frees whichever old versions no reader
+/// is looking at anymore.
+pub struct RcuWriteGuard<'a, T, const SLICES: usize = 6> {
+    cane: &'a RcuCandyCane<T, SLICES>,
+    retired: MutexGuard<'a, Vec<(usize, *mut Version<T>)>>,
+    version: Version<T>,
+    changed: bool,
+}
+
+impl<'a, T, const SLICES: usize> RcuWriteGuard<'a, T, SLICES> {
+    pub fn len(&self) -> usize {
+        self.version.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.version.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.version.chunks().flatten()
+    }
+
+    /// Drops every element. No chunk is cloned for it.
+    pub fn clear(&mut self) {
+        self.changed = true;
+        self.version.chunks.clear();
+        self.version.len = 0;
+    }
+}
+
+impl<'a, T: Clone, const SLICES: usize> RcuWriteGuard<'a, T, SLICES> {
+    /// Clones the chunk `index` is in, unless that was done already.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (chunk, at) = self.version.locate(index)?;
+        self.changed = true;
+        Some(&mut Arc::make_mut(&mut self.version.chunks[chunk])[at])
+    }
+
+    /// Clones every chunk, unless that was done already.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.changed = true;
+        self.version.chunks.iter_mut().flat_map(|chunk| Arc::make_mut(chunk).iter_mut())
+    }
+
+    /// Clones the last chunk, unless that was done already, or
+    /// it's full, in which case this starts a new one instead.
+    pub fn push(&mut self, value: T) {
+        let Version { chunks, chunk_len, len } = &mut self.version;
+        self.changed = true;
+
+        if *len % *chunk_len == 0 {
+            chunks.push(Arc::new(Vec::with_capacity(*chunk_len)));
+        }
+        Arc::make_mut(chunks.last_mut().unwrap()).push(value);
+        *len += 1;
+    }
+
+    /// Clones the last chunk, unless that was done already.
+    pub fn pop(&mut self) -> Option<T> {
+        let Version { chunks, len, .. } = &mut self.version;
+        let last = chunks.last_mut()?;
+        self.changed = true;
+
+        let value = Arc::make_mut(last).pop();
+        if last.is_empty() {
+            chunks.pop();
+        }
+        *len -= 1;
+        value
+    }
+
+    /// Clones the chunk the new last element is in, unless that
+    /// was done already, or it stays whole.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.version.len {
+            return;
+        }
+        self.changed = true;
+
+        let Version { chunks, chunk_len, .. } = &mut self.version;
+        let kept = len.div_ceil(*chunk_len);
+        chunks.truncate(kept);
+        if let Some(last) = chunks.last_mut() {
+            let keep = len - (kept - 1) * *chunk_len;
+            if keep < last.len() {
+                Arc::make_mut(last).truncate(keep);
+            }
+        }
+        self.version.len = len;
+    }
+}
+
+impl<'a, T, const SLICES: usize> Drop for RcuWriteGuard<'a, T, SLICES> {
+    fn drop(&mut self) {
+        let cane = self.cane;
+
+        if self.changed {
+            let version = Version {
+                chunks: mem::take(&mut self.version.chunks),
+                ..self.version
+            };
+            let old = cane.current.swap(Box::into_raw(Box::new(version)), Ordering::SeqCst);
+            let epoch = cane.epoch.fetch_add(1, Ordering::SeqCst);
+            self.retired.push((epoch, old));
+        }
+
+        cane.reclaim(&mut self.retired);
+        deadlock::released(cane.id(), Held::Exclusive);
+    }
+}
@@ -1,7 +1,15 @@
-use parking_lot::lock_api::{RawRwLock, RawMutex};
+use parking_lot::lock_api::{RawRwLock, RawRwLockUpgrade, RawMutex};
 use std::cell::UnsafeCell;
-use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+use std::marker::PhantomData;
 use parking_lot::lock_api::{Mutex, MutexGuard};
+use crate::deadlock::{self, Held};
+use crate::stats::{ChunkCounters, Timer};
+use crate::adaptive::Window;
+use crate::steal::{StealSlot, STEAL_SLOTS};
+use crate::wait::Released;
+use std::mem::ManuallyDrop;
+use std::time::Instant;
 
 ///
 /// SAFETY: The contents of `data` should only
@@ -16,10 +24,44 @@ use parking_lot::lock_api::{Mutex, MutexGuard};
 /// this struct, and `data` is only valid to be
 /// read while `all_state` allows us to read.
 ///
+/// Where the chunk is can change under the shared lock, by
+/// inserting or removing elements, so `data`, `start` and
+/// `length` are atomic.
+///
 pub struct SliceTracker<M: RawMutex, T> {
-    pub(crate) data: NonNull<UnsafeCell<T>>,
-    pub(crate) length: usize,
+    /// Only changed while the chunk is locked.
+    pub(crate) data: AtomicPtr<UnsafeCell<T>>,
+    /// The index of the first element in the whole cane. Moves
+    /// whenever an earlier chunk gains or loses an element.
+    pub(crate) start: AtomicUsize,
+    /// Only changed while the chunk is locked. Appends to the
+    /// last chunk aren't counted until it's next edited, see the
+    /// cane's `settled`.
+    pub(crate) length: AtomicUsize,
     pub(crate) lock: Mutex<M, ()>,
+    pub(crate) stats: ChunkCounters,
+    /// Only kept up to date when the cane is adaptive,
+    /// since timing every chunk isn't free.
+    pub(crate) window: Option<Window>,
+    /// Where the parts of this chunk being iterated are
+    /// published, so that idle iterators can borrow them.
+    pub(crate) steal: [StealSlot; STEAL_SLOTS],
+    /// Iterators which stole part of this chunk and still have
+    /// to come back for the rest. Its elements can't be moved
+    /// around until they have.
+    pub(crate) revisits: AtomicUsize,
+    /// Bumped before and after an element is inserted or removed,
+    /// so that thieves can tell the chunk changed under them.
+    pub(crate) edits: AtomicUsize,
+    /// Odd while the chunk's elements may be written under the
+    /// shared lock, so that optimistic readers, who don't lock the
+    /// chunk, can tell whether they changed under them. Marked by
+    /// each writer, and made even again when the lock is let go.
+    pub(crate) seq: AtomicUsize,
+    /// The `thread_token` of whoever holds the chunk's lock, or zero,
+    /// so that optimistic readers can tell they'd be waiting for
+    /// themselves in release builds too.
+    pub(crate) holder: AtomicUsize,
 }
 
 // SAFETY: T need not be Sync, since we check for
@@ -33,26 +75,132 @@ impl<M: RawMutex, T> SliceTracker<M, T> {
     /// SAFETY: `data`, and `length` must be valid
     /// and not overlap with any other `SliceTracker`s
     /// in the same collection.
-    pub unsafe fn new(data: *const UnsafeCell<T>, length: usize) -> Self {
+    pub unsafe fn new(data: *const UnsafeCell<T>, start: usize, length: usize, adaptive: bool) -> Self {
         Self {
-            data: NonNull::new_unchecked(data as *mut _),
-            length,
+            data: AtomicPtr::new(data as *mut _),
+            start: AtomicUsize::new(start),
+            length: AtomicUsize::new(length),
             lock: Default::default(),
+            stats: Default::default(),
+            window: if adaptive { Some(Window::default()) } else { None },
+            steal: Default::default(),
+            revisits: AtomicUsize::new(0),
+            edits: AtomicUsize::new(0),
+            seq: AtomicUsize::new(0),
+            holder: AtomicUsize::new(0),
         }
     }
 
-    pub fn lock(&self) -> MutexGuard<'_, M, ()> {
-        self.lock.lock()
+    pub(crate) fn data(&self) -> *mut UnsafeCell<T> {
+        self.data.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn start(&self) -> usize {
+        self.start.load(Ordering::Relaxed)
+    }
+
+    /// Acquires, so that elements an edit put in the chunk
+    /// can be read by thieves, who don't lock it.
+    pub(crate) fn length(&self) -> usize {
+        self.length.load(Ordering::Acquire)
+    }
+
+    /// Must be called before writing any of the chunk's elements
+    /// under the shared lock, while the chunk's lock is held, by
+    /// us or by whoever we stole part of it from.
+    pub(crate) fn writing(&self) {
+        self.seq.fetch_or(1, Ordering::Relaxed);
+        // Keeps the writes that follow from being seen before it.
+        fence(Ordering::Release);
     }
 
-    pub fn try_lock(&self) -> Option<MutexGuard<'_, M, ()>> {
-        self.lock.try_lock()
+    /// `cane` and `chunk` identify this tracker to the
+    /// self-deadlock checks in debug builds. `released` is
+    /// notified once the returned guard unlocks the chunk.
+    pub fn lock<'a>(&'a self, cane: usize, chunk: usize, released: &'a Released) -> ChunkGuard<'a, M> {
+        deadlock::check_chunk(cane, chunk);
+        self.stats.blocking_locks.increment();
+        ChunkGuard::new(self.lock.lock(), self, cane, chunk, released, true)
+    }
+
+    pub fn try_lock<'a>(&'a self, cane: usize, chunk: usize, released: &'a Released) -> Option<ChunkGuard<'a, M>> {
+        match self.lock.try_lock() {
+            Some(guard) => {
+                self.stats.try_lock_successes.increment();
+                Some(ChunkGuard::new(guard, self, cane, chunk, released, false))
+            }
+            None => {
+                self.stats.try_lock_failures.increment();
+                None
+            }
+        }
+    }
+}
+
+pub struct ChunkGuard<'a, M: RawMutex> {
+    _hold: Timer<'a>,
+    /// Dropped by hand, so that `released` is only
+    /// notified once the chunk is actually free.
+    guard: ManuallyDrop<MutexGuard<'a, M, ()>>,
+    window: Option<(&'a Window, Instant)>,
+    seq: &'a AtomicUsize,
+    holder: &'a AtomicUsize,
+    released: &'a Released,
+    cane: usize,
+    chunk: usize,
+}
+
+impl<'a, M: RawMutex> ChunkGuard<'a, M> {
+    fn new<T>(
+        guard: MutexGuard<'a, M, ()>,
+        tracker: &'a SliceTracker<M, T>,
+        cane: usize,
+        chunk: usize,
+        released: &'a Released,
+        blocked: bool,
+    ) -> Self {
+        deadlock::acquired(cane, Held::Chunk(chunk));
+        tracker.holder.store(deadlock::thread_token(), Ordering::Relaxed);
+        Self {
+            _hold: tracker.stats.hold_nanos.time(),
+            guard: ManuallyDrop::new(guard),
+            window: tracker.window.as_ref().map(|window| (window, window.acquired(blocked))),
+            seq: &tracker.seq,
+            holder: &tracker.holder,
+            released,
+            cane,
+            chunk,
+        }
+    }
+}
+
+impl<'a, M: RawMutex> Drop for ChunkGuard<'a, M> {
+    fn drop(&mut self) {
+        if let Some((window, acquired)) = self.window {
+            window.released(acquired);
+        }
+        deadlock::released(self.cane, Held::Chunk(self.chunk));
+        self.holder.store(0, Ordering::Relaxed);
+
+        // Anyone who stole part of the chunk to write to is done
+        // with it by now, so every write to it is over.
+        let seq = self.seq.load(Ordering::Relaxed);
+        if seq % 2 == 1 {
+            self.seq.store(seq + 1, Ordering::Release);
+        }
+
+        // SAFETY: `guard` is never touched again.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        self.released.notify();
     }
 }
 
 pub struct LockGuard<'a, R: RawRwLock> {
     pub(crate) rwlock: &'a R,
     pub(crate) kind: LockGuardType,
+    /// Only lets the guard be sent to another thread
+    /// if `R` may be unlocked from a different thread.
+    _marker: PhantomData<R::GuardMarker>,
 }
 
 impl<'a, R: RawRwLock> LockGuard<'a, R> {
@@ -61,10 +209,12 @@ impl<'a, R: RawRwLock> LockGuard<'a, R> {
             LockGuardType::Read => rwlock.lock_shared(),
             LockGuardType::Write => rwlock.lock_exclusive(),
         }
+        deadlock::acquired(deadlock::id_of(rwlock), lock_type.held());
 
         Self {
             rwlock,
             kind: lock_type,
+            _marker: PhantomData,
         }
     }
 
@@ -74,9 +224,11 @@ impl<'a, R: RawRwLock> LockGuard<'a, R> {
             LockGuardType::Write => rwlock.try_lock_exclusive(),
         };
         if succeeded {
+            deadlock::acquired(deadlock::id_of(rwlock), lock_type.held());
             Some(Self {
                 rwlock,
                 kind: lock_type,
+                _marker: PhantomData,
             })
         } else {
             None
@@ -86,6 +238,7 @@ impl<'a, R: RawRwLock> LockGuard<'a, R> {
 
 impl<'a, R: RawRwLock> Drop for LockGuard<'a, R> {
     fn drop(&mut self) {
+        deadlock::released(deadlock::id_of(self.rwlock), self.kind.held());
         unsafe {
             match self.kind {
                 LockGuardType::Read => self.rwlock.unlock_shared(),
@@ -95,8 +248,61 @@ impl<'a, R: RawRwLock> Drop for LockGuard<'a, R> {
     }
 }
 
+/// The upgradable lock on a cane, which shares with readers but
+/// not with writers, nor with other upgradable locks.
+pub struct UpgradableGuard<'a, R: RawRwLockUpgrade> {
+    pub(crate) rwlock: &'a R,
+    _marker: PhantomData<R::GuardMarker>,
+}
+
+impl<'a, R: RawRwLockUpgrade> UpgradableGuard<'a, R> {
+    pub fn lock(rwlock: &'a R) -> Self {
+        rwlock.lock_upgradable();
+        deadlock::acquired(deadlock::id_of(rwlock), Held::Upgradable);
+
+        Self {
+            rwlock,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Waits for every reader to leave, and turns this into the
+    /// exclusive lock without letting anyone else take it first.
+    pub fn upgrade(self) -> LockGuard<'a, R> {
+        let this = ManuallyDrop::new(self);
+        let cane = deadlock::id_of(this.rwlock);
+        // SAFETY: We hold the upgradable lock, and won't unlock it
+        // again, since `this` isn't dropped.
+        unsafe { this.rwlock.upgrade() };
+        deadlock::released(cane, Held::Upgradable);
+        deadlock::acquired(cane, Held::Exclusive);
+
+        LockGuard {
+            rwlock: this.rwlock,
+            kind: LockGuardType::Write,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, R: RawRwLockUpgrade> Drop for UpgradableGuard<'a, R> {
+    fn drop(&mut self) {
+        deadlock::released(deadlock::id_of(self.rwlock), Held::Upgradable);
+        unsafe { self.rwlock.unlock_upgradable() };
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Hash)]
 pub enum LockGuardType {
     Read,
     Write,
 }
+
+impl LockGuardType {
+    fn held(self) -> Held {
+        match self {
+            LockGuardType::Read => Held::Shared,
+            LockGuardType::Write => Held::Exclusive,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Contention counters, collected with the `stats` feature.
+//!
+//! Without the feature every counter is zero sized and
+//! every method on them is empty, so they compile away.
+
+#[cfg(feature = "stats")]
+use std::convert::TryInto;
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "stats")]
+use std::time::{Duration, Instant};
+#[cfg(not(feature = "stats"))]
+use std::marker::PhantomData;
+
+#[derive(Default)]
+pub(crate) struct Counter {
+    #[cfg(feature = "stats")]
+    value: AtomicU64,
+}
+
+impl Counter {
+    #[inline(always)]
+    pub(crate) fn add(&self, _n: u64) {
+        #[cfg(feature = "stats")]
+        self.value.fetch_add(_n, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn increment(&self) {
+        self.add(1);
+    }
+
+    /// Adds the time until the returned timer is dropped,
+    /// in nanoseconds.
+    #[inline(always)]
+    pub(crate) fn time(&self) -> Timer<'_> {
+        Timer {
+            #[cfg(feature = "stats")]
+            counter: self,
+            #[cfg(feature = "stats")]
+            started: Instant::now(),
+            #[cfg(not(feature = "stats"))]
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "stats")]
+    fn reset(&self) {
+        self.value.store(0, Ordering::Relaxed);
+    }
+}
+
+pub(crate) struct Timer<'a> {
+    #[cfg(feature = "stats")]
+    counter: &'a Counter,
+    #[cfg(feature = "stats")]
+    started: Instant,
+    #[cfg(not(feature = "stats"))]
+    _marker: PhantomData<&'a Counter>,
+}
+
+#[cfg(feature = "stats")]
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed().as_nanos();
+        self.counter.add(elapsed.try_into().unwrap_or(u64::MAX));
+    }
+}
+
+/// Kept by each `SliceTracker`.
+#[derive(Default)]
+pub(crate) struct ChunkCounters {
+    pub(crate) try_lock_successes: Counter,
+    pub(crate) try_lock_failures: Counter,
+    pub(crate) blocking_locks: Counter,
+    pub(crate) steals: Counter,
+    pub(crate) hold_nanos: Counter,
+}
+
+#[derive(Default)]
+pub(crate) struct CaneCounters {
+    pub(crate) readers_gated: Counter,
+    pub(crate) writes: Counter,
+    pub(crate) write_wait_nanos: Counter,
+    pub(crate) write_hold_nanos: Counter,
+}
+
+/// How contended a single chunk has been.
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkStats {
+    /// Times an iterator got this chunk without waiting.
+    pub try_lock_successes: u64,
+    /// Times an iterator found this chunk taken and moved on.
+    pub try_lock_failures: u64,
+    /// Times an iterator had nothing else left to visit,
+    /// and so waited for this chunk.
+    pub blocking_locks: u64,
+    /// Times part of this chunk was lent to an idle iterator
+    /// while another one held it.
+    pub steals: u64,
+    /// How long the chunk was locked for in total.
+    pub hold_time: Duration,
+}
+
+/// A snapshot of the counters of a cane, from [`RawCandyCane::stats`].
+///
+/// Every counter is read on its own, so a snapshot taken
+/// while the cane is in use may be slightly inconsistent.
+///
+/// [`RawCandyCane::stats`]: crate::RawCandyCane::stats
+#[cfg(feature = "stats")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CandyCaneStats {
+    /// One per chunk, in order.
+    pub chunks: Vec<ChunkStats>,
+    /// Readers which had to wait at the gate for a writer.
+    pub readers_gated: u64,
+    /// Times the exclusive lock was taken, by `write()` or by
+    /// appends which didn't fit in the spare capacity.
+    pub writes: u64,
+    /// How long those waited for the exclusive lock in total.
+    pub write_wait_time: Duration,
+    /// How long write guards were alive for in total.
+    pub write_hold_time: Duration,
+}
+
+#[cfg(feature = "stats")]
+impl ChunkCounters {
+    pub(crate) fn snapshot(&self) -> ChunkStats {
+        ChunkStats {
+            try_lock_successes: self.try_lock_successes.get(),
+            try_lock_failures: self.try_lock_failures.get(),
+            blocking_locks: self.blocking_locks.get(),
+            steals: self.steals.get(),
+            hold_time: Duration::from_nanos(self.hold_nanos.get()),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.try_lock_successes.reset();
+        self.try_lock_failures.reset();
+        self.blocking_locks.reset();
+        self.steals.reset();
+        self.hold_nanos.reset();
+    }
+}
+
+#[cfg(feature = "stats")]
+impl CaneCounters {
+    pub(crate) fn snapshot(&self, chunks: Vec<ChunkStats>) -> CandyCaneStats {
+        CandyCaneStats {
+            chunks,
+            readers_gated: self.readers_gated.get(),
+            writes: self.writes.get(),
+            write_wait_time: Duration::from_nanos(self.write_wait_nanos.get()),
+            write_hold_time: Duration::from_nanos(self.write_hold_nanos.get()),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.readers_gated.reset();
+        self.writes.reset();
+        self.write_wait_nanos.reset();
+        self.write_hold_nanos.reset();
+    }
+}
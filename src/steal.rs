@@ -0,0 +1,218 @@
+//! Lending the unvisited end of a busy chunk to idle iterators.
+//!
+//! Each iterator still visits every element of its range, so a
+//! stolen part isn't visited on its owner's behalf. It's lent out,
+//! so that the thief can get on with its own pass through it while
+//! the owner works its way up to it. The owner then waits for the
+//! lent part to be given back before visiting it itself.
+//!
+//! Whoever is iterating part of a chunk publishes what it has left
+//! in one of the chunk's slots, as a packed `cursor..end` which it
+//! advances a batch at a time. A thief shrinks `end` to the middle of
+//! what's left, and may publish the part it got in another slot of
+//! the same chunk, so that it can be split again.
+
+use crate::wait::{Backoff, Released, WaitStrategy};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+/// How many parts of a chunk may be in flight at once. The first
+/// slot is always used by whoever holds the chunk's lock.
+pub(crate) const STEAL_SLOTS: usize = 4;
+/// The fewest elements claimed from a slot at a time, unless
+/// fewer are left. Smaller batches leave more for thieves, but
+/// cost more atomics.
+pub(crate) const BATCH: usize = 32;
+/// Larger parts are claimed this fraction at a time instead,
+/// so that long chunks don't cost an atomic every `BATCH`.
+const BATCH_SHARE: usize = 8;
+/// Parts smaller than this aren't worth stealing.
+pub(crate) const MIN_STEAL: usize = 2;
+
+fn pack(range: Range<usize>) -> u64 {
+    ((range.start as u64) << 32) | range.end as u64
+}
+
+fn unpack(packed: u64) -> Range<usize> {
+    (packed >> 32) as usize..(packed & u64::from(u32::MAX)) as usize
+}
+
+/// Whether a chunk is small enough for its ranges to be packed.
+pub(crate) fn can_publish(len: usize) -> bool {
+    len <= u32::MAX as usize
+}
+
+#[derive(Default)]
+pub(crate) struct StealSlot {
+    range: AtomicU64,
+    /// Parts stolen from this slot which haven't been given back.
+    outstanding: AtomicUsize,
+    in_use: AtomicBool,
+}
+
+impl StealSlot {
+    /// Takes a free slot for a thief to publish its part in.
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.in_use
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Only once nothing is left and everything was given back.
+    pub(crate) fn release(&self) {
+        self.in_use.store(false, Ordering::Release);
+    }
+
+    /// `range` must be in chunk local indices that fit in a `u32`.
+    pub(crate) fn publish(&self, range: Range<usize>) {
+        self.range.store(pack(range), Ordering::SeqCst);
+    }
+
+    /// Takes up to `BATCH` elements, or a `BATCH_SHARE`th of
+    /// what's left if that's more, off the front of it.
+    pub(crate) fn claim(&self) -> Option<Range<usize>> {
+        let mut current = self.range.load(Ordering::SeqCst);
+        loop {
+            let range = unpack(current);
+            if range.is_empty() {
+                return None;
+            }
+
+            let end = range.end.min(range.start + BATCH.max(range.len() / BATCH_SHARE));
+            match self.range.compare_exchange_weak(current, pack(end..range.end), Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return Some(range.start..end),
+                Err(x) => current = x,
+            }
+        }
+    }
+
+    /// Takes the back half of what's left, if all of it lies
+    /// `within` the thief's own range. Must be given back
+    /// through `give_back` once the thief is done with it.
+    ///
+    /// `released` is the cane's, like for `give_back`.
+    pub(crate) fn steal(&self, within: &Range<usize>, released: &Released) -> Option<Range<usize>> {
+        // Counted first, so that an owner which sees nothing
+        // outstanding can't have anything stolen after that.
+        self.outstanding.fetch_add(1, Ordering::SeqCst);
+
+        let current = self.range.load(Ordering::SeqCst);
+        let range = unpack(current);
+        let middle = (range.start + range.len() / 2).max(within.start);
+
+        let stolen = middle..range.end;
+        if range.len() >= MIN_STEAL && !stolen.is_empty() && stolen.end <= within.end
+            && self.range.compare_exchange(current, pack(range.start..middle), Ordering::SeqCst, Ordering::SeqCst).is_ok()
+        {
+            return Some(stolen);
+        }
+
+        // The owner may have seen it counted, and be waiting.
+        self.give_back(released);
+        None
+    }
+
+    /// Wakes the owner through `released`, the cane's, in case
+    /// it's waiting for thieves.
+    pub(crate) fn give_back(&self, released: &Released) {
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+        released.notify();
+    }
+
+    /// Waits for everything stolen from this slot to be given
+    /// back, and returns where what's left of it now ends.
+    /// There's no lock to block on, so `Block` sleeps until
+    /// something's given back, like `Park`.
+    ///
+    /// Only valid once `claim` returned `None`.
+    pub(crate) fn wait_for_thieves(&self, wait: WaitStrategy, released: &Released) -> usize {
+        let given_back = || self.outstanding.load(Ordering::SeqCst) == 0;
+        match wait {
+            WaitStrategy::Spin => {
+                let mut backoff = Backoff::default();
+                while !given_back() {
+                    backoff.snooze();
+                }
+            }
+            WaitStrategy::Block | WaitStrategy::Park => loop {
+                let seen = released.generation();
+                if given_back() {
+                    break;
+                }
+                released.park(seen);
+            },
+        }
+
+        unpack(self.range.load(Ordering::SeqCst)).end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StealSlot, BATCH};
+    use crate::wait::{Released, WaitStrategy};
+    use std::time::Duration;
+
+    #[test]
+    fn claims_in_batches() {
+        let (slot, released) = (StealSlot::default(), Released::default());
+        slot.publish(3..BATCH + 10);
+        assert_eq!(slot.claim(), Some(3..BATCH + 3));
+        assert_eq!(slot.claim(), Some(BATCH + 3..BATCH + 10));
+        assert_eq!(slot.claim(), None);
+        assert_eq!(slot.wait_for_thieves(WaitStrategy::Spin, &released), BATCH + 10);
+    }
+
+    #[test]
+    fn claims_a_share_of_long_parts() {
+        let (slot, released) = (StealSlot::default(), Released::default());
+        slot.publish(0..BATCH * 16);
+        assert_eq!(slot.claim(), Some(0..BATCH * 2));
+        assert_eq!(slot.steal(&(0..BATCH * 16), &released), Some(BATCH * 9..BATCH * 16));
+        assert_eq!(slot.claim(), Some(BATCH * 2..BATCH * 3));
+    }
+
+    #[test]
+    fn steals_the_back_half() {
+        let (slot, released) = (StealSlot::default(), Released::default());
+        slot.publish(0..10);
+        assert_eq!(slot.steal(&(0..10), &released), Some(5..10));
+        assert_eq!(slot.steal(&(0..10), &released), Some(2..5));
+        // Only what's within the thief's own range.
+        assert_eq!(slot.steal(&(3..10), &released), None);
+        assert_eq!(slot.steal(&(0..1), &released), None);
+
+        assert_eq!(slot.claim(), Some(0..2));
+        assert_eq!(slot.claim(), None);
+        slot.give_back(&released);
+        slot.give_back(&released);
+        assert_eq!(slot.wait_for_thieves(WaitStrategy::Spin, &released), 2);
+    }
+
+    #[test]
+    fn nothing_left_to_steal() {
+        let (slot, released) = (StealSlot::default(), Released::default());
+        slot.publish(0..1);
+        assert_eq!(slot.steal(&(0..1), &released), None);
+        slot.publish(4..4);
+        assert_eq!(slot.steal(&(0..10), &released), None);
+        assert_eq!(slot.wait_for_thieves(WaitStrategy::Spin, &released), 4);
+    }
+
+    #[test]
+    fn sleeps_until_given_back() {
+        let (slot, released) = (StealSlot::default(), Released::default());
+        slot.publish(0..10);
+        assert_eq!(slot.steal(&(0..10), &released), Some(5..10));
+        assert_eq!(slot.claim(), Some(0..5));
+        assert_eq!(slot.claim(), None);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                slot.give_back(&released);
+            });
+            assert_eq!(slot.wait_for_thieves(WaitStrategy::Park, &released), 5);
+        });
+    }
+}
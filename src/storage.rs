@@ -0,0 +1,324 @@
+//! Where a cane keeps its elements.
+
+use std::cell::UnsafeCell;
+
+/// Where a cane keeps its elements, picked with the last parameter
+/// of [`RawCandyCane`]. Either [`Contiguous`] or [`Segmented`].
+///
+/// [`RawCandyCane`]: crate::RawCandyCane
+pub trait Storage: sealed::Sealed {}
+
+/// Keeps every element in one vec, which is split into chunks.
+/// Growing it past its capacity moves every element, and every
+/// chunk is rebuilt. This is the default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Contiguous;
+
+/// Gives each chunk an allocation of its own. Appending past the
+/// last one's capacity starts a new chunk instead of moving any
+/// elements, so their addresses stay the same until the next
+/// [`write`], and only the chunks which changed are rebuilt.
+///
+/// Elements can also be inserted and removed anywhere, with
+/// [`insert`] and [`remove`], which only lock the chunk they're
+/// in. Chunks that grow or shrink too far are split or merged,
+/// see [`ChunkBounds`].
+///
+/// A range over several chunks isn't one slice anymore, so
+/// [`write_range`] and [`as_mut_slice`] need [`Contiguous`].
+///
+/// [`write`]: crate::RawCandyCane::write
+/// [`insert`]: crate::RawCandyCane::insert
+/// [`remove`]: crate::RawCandyCane::remove
+/// [`write_range`]: crate::RawCandyCane::write_range
+/// [`as_mut_slice`]: crate::RawCandyCane::as_mut_slice
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Segmented;
+
+impl Storage for Contiguous {}
+impl Storage for Segmented {}
+
+/// When a [`Segmented`] cane splits or merges a chunk, set with
+/// [`RawCandyCane::set_chunk_bounds`].
+///
+/// Doing so needs the exclusive lock, so it's only done if that
+/// can be had without waiting, right after the insert or remove
+/// which took the chunk past a bound. Otherwise the chunk stays
+/// as it is until the next edit to it, or the next [`write`].
+///
+/// [`RawCandyCane::set_chunk_bounds`]: crate::RawCandyCane::set_chunk_bounds
+/// [`write`]: crate::RawCandyCane::write
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkBounds {
+    /// Chunks longer than this are split into as few chunks
+    /// as fit under it.
+    pub split_above: usize,
+    /// Chunks shorter than this are merged into the shorter of
+    /// their neighbours, unless that would make the merged
+    /// chunk longer than half of `split_above`.
+    pub merge_below: usize,
+}
+
+impl Default for ChunkBounds {
+    fn default() -> Self {
+        Self {
+            split_above: 8192,
+            merge_below: 64,
+        }
+    }
+}
+
+/// New segments get at least this much capacity, so that
+/// pushing one at a time doesn't start a chunk every time.
+const MIN_SEGMENT: usize = 64;
+
+pub(crate) mod sealed {
+    use super::{Contiguous, Segmented, Segments};
+    use std::cell::UnsafeCell;
+
+    pub trait Sealed: 'static {
+        type Buffer<T>: Buffer<T>;
+    }
+
+    impl Sealed for Contiguous {
+        type Buffer<T> = Vec<UnsafeCell<T>>;
+    }
+
+    impl Sealed for Segmented {
+        type Buffer<T> = Segments<T>;
+    }
+
+    /// Elements past `len()` may have been appended through `spare`
+    /// under the shared lock. Before anything but `spare` and `parts`
+    /// is called, the buffer must be told about them with `settle`.
+    pub trait Buffer<T> {
+        /// Whether `from_vec` keeps the vec's allocation, so that
+        /// elements which didn't move in it didn't move at all.
+        const KEEPS_VEC: bool;
+
+        /// `data`, in about `chunks` parts.
+        fn from_vec(data: Vec<UnsafeCell<T>>, chunks: usize) -> Self;
+
+        /// Every element, in order. Leaves the buffer empty.
+        fn take(&mut self) -> Vec<UnsafeCell<T>>;
+
+        fn len(&self) -> usize;
+
+        /// Where each chunk starts, and how long it is, with
+        /// `chunks` of them if the elements can be moved between
+        /// them. Never empty. Each pointer is valid for the spare
+        /// capacity past the chunk's end as well.
+        fn parts(&self, chunks: usize) -> Vec<(*const UnsafeCell<T>, usize)>;
+
+        /// Where the element at `len` goes, when the last chunk is
+        /// `last_len` long, and how many elements fit from there
+        /// on without allocating.
+        fn spare(&self, len: usize, last_len: usize) -> (*mut UnsafeCell<T>, usize);
+
+        /// SAFETY: Every element up to `len` must have been written.
+        unsafe fn settle(&mut self, len: usize);
+
+        fn extend_from(&mut self, values: impl Iterator<Item = T>);
+
+        fn reserve(&mut self, additional: usize);
+    }
+}
+
+impl<T> sealed::Buffer<T> for Vec<UnsafeCell<T>> {
+    const KEEPS_VEC: bool = true;
+
+    fn from_vec(data: Vec<UnsafeCell<T>>, _chunks: usize) -> Self {
+        data
+    }
+
+    fn take(&mut self) -> Vec<UnsafeCell<T>> {
+        std::mem::take(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn parts(&self, chunks: usize) -> Vec<(*const UnsafeCell<T>, usize)> {
+        let per_chunk = self.len() / chunks;
+        let last_extra = self.len() % chunks;
+
+        // The pointers come from the vec rather than a slice of
+        // it, so that they're valid for its spare capacity too.
+        (0..chunks)
+            .map(|index| {
+                let length = if index == chunks - 1 { per_chunk + last_extra } else { per_chunk };
+                // SAFETY: Each chunk starts where the previous
+                // one ended, so all of them are in bounds.
+                (unsafe { self.as_ptr().add(index * per_chunk) }, length)
+            })
+            .collect()
+    }
+
+    fn spare(&self, len: usize, _last_len: usize) -> (*mut UnsafeCell<T>, usize) {
+        // SAFETY: Everything up to `len` was written, so
+        // it's at most the capacity.
+        (unsafe { self.as_ptr().add(len).cast_mut() }, self.capacity() - len)
+    }
+
+    unsafe fn settle(&mut self, len: usize) {
+        self.set_len(len);
+    }
+
+    fn extend_from(&mut self, values: impl Iterator<Item = T>) {
+        self.extend(values.map(UnsafeCell::new));
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+}
+
+/// The buffer of a [`Segmented`] cane. Only the last segment
+/// is ever appended to, and none of them is ever reallocated
+/// unless it's empty, or something is inserted into it.
+///
+/// Each segment is in a cell of its own, so that it can be
+/// changed while its chunk is locked, under the shared lock.
+/// Everything else only looks at the segments under the
+/// exclusive lock, except for `spare`, which only looks at
+/// the last one under the tail lock.
+#[derive(Default)]
+pub struct Segments<T> {
+    segments: Vec<UnsafeCell<Vec<UnsafeCell<T>>>>,
+}
+
+impl<T> Segments<T> {
+    /// Only to be used while the chunk is locked.
+    pub(crate) fn segment(&self, chunk: usize) -> *mut Vec<UnsafeCell<T>> {
+        self.segments[chunk].get()
+    }
+
+    fn last(&mut self) -> &mut Vec<UnsafeCell<T>> {
+        self.segments.last_mut().expect("there's always a segment").get_mut()
+    }
+
+    /// Gives the last segment room for `capacity` elements past
+    /// its end, by growing it if it's empty, and otherwise by
+    /// starting a new one after it.
+    fn start_segment(&mut self, capacity: usize) {
+        let last = self.last();
+        if last.is_empty() {
+            // Nothing in it can move.
+            last.reserve_exact(capacity);
+        } else {
+            self.segments.push(UnsafeCell::new(Vec::with_capacity(capacity)));
+        }
+    }
+
+    fn room(&mut self) -> usize {
+        let last = self.last();
+        last.capacity() - last.len()
+    }
+
+    /// Splits chunk `chunk` if it's grown past `bounds`, or
+    /// merges it into a neighbour if it's shrunk below them.
+    /// Everything must be settled.
+    pub(crate) fn resegment(&mut self, chunk: usize, bounds: ChunkBounds) {
+        let split_above = bounds.split_above.max(1);
+        let len = match self.segments.get_mut(chunk) {
+            Some(segment) => segment.get_mut().len(),
+            // Already merged away since.
+            None => return,
+        };
+
+        if len > split_above {
+            // The front part stays where it is.
+            let segment = self.segments[chunk].get_mut();
+            let parts = len.div_ceil(split_above);
+            let mut backs = (1..parts).rev().map(|part| segment.split_off(len * part / parts)).collect::<Vec<_>>();
+            backs.reverse();
+            self.segments.splice(chunk + 1..chunk + 1, backs.into_iter().map(UnsafeCell::new));
+        } else if len < bounds.merge_below && self.segments.len() > 1 {
+            let before = chunk.checked_sub(1).map(|x| (x, self.segments[x].get_mut().len()));
+            let after = self.segments.get_mut(chunk + 1).map(|x| (chunk + 1, x.get_mut().len()));
+            let (neighbour, neighbour_len) = match (before, after) {
+                (Some(before), Some(after)) => if after.1 < before.1 { after } else { before },
+                (before, after) => before.or(after).expect("there's more than one segment"),
+            };
+
+            if len + neighbour_len <= split_above / 2 {
+                let (front, back) = (chunk.min(neighbour), chunk.max(neighbour));
+                let mut back = self.segments.remove(back).into_inner();
+                self.segments[front].get_mut().append(&mut back);
+            }
+        }
+    }
+}
+
+impl<T> sealed::Buffer<T> for Segments<T> {
+    const KEEPS_VEC: bool = false;
+
+    fn from_vec(data: Vec<UnsafeCell<T>>, chunks: usize) -> Self {
+        let per_chunk = data.len() / chunks;
+        let mut data = data.into_iter();
+
+        let segments = (0..chunks)
+            .map(|index| {
+                let length = if index == chunks - 1 { data.len() } else { per_chunk };
+                UnsafeCell::new(data.by_ref().take(length).collect())
+            })
+            .collect();
+
+        Self { segments }
+    }
+
+    fn take(&mut self) -> Vec<UnsafeCell<T>> {
+        let mut data = Vec::with_capacity(self.len());
+        self.segments.drain(..).for_each(|segment| data.extend(segment.into_inner()));
+        data
+    }
+
+    fn len(&self) -> usize {
+        // SAFETY: Only called under the exclusive lock.
+        self.segments.iter().map(|segment| unsafe { &*segment.get() }.len()).sum()
+    }
+
+    fn parts(&self, _chunks: usize) -> Vec<(*const UnsafeCell<T>, usize)> {
+        // SAFETY: As above.
+        let segments = self.segments.iter().map(|segment| unsafe { &*segment.get() });
+        segments.map(|segment| (segment.as_ptr(), segment.len())).collect()
+    }
+
+    fn spare(&self, _len: usize, last_len: usize) -> (*mut UnsafeCell<T>, usize) {
+        // SAFETY: The tail lock is held, and everyone changing
+        // the last segment takes it as well.
+        let last = unsafe { &*self.segments.last().expect("there's always a segment").get() };
+        // SAFETY: As for `Contiguous`, within the last segment.
+        (unsafe { last.as_ptr().add(last_len).cast_mut() }, last.capacity() - last_len)
+    }
+
+    unsafe fn settle(&mut self, len: usize) {
+        // Empty once everything was taken.
+        if self.segments.is_empty() {
+            return;
+        }
+        let others = self.len() - self.last().len();
+        let last = self.last();
+        last.set_len(len - others);
+    }
+
+    fn extend_from(&mut self, values: impl Iterator<Item = T>) {
+        let mut values = values.peekable();
+        while values.peek().is_some() {
+            if self.room() == 0 {
+                let last = self.last().capacity();
+                self.start_segment(values.size_hint().0.max(last).max(MIN_SEGMENT));
+            }
+            let last = self.last();
+            let room = last.capacity() - last.len();
+            last.extend(values.by_ref().take(room).map(UnsafeCell::new));
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        if self.room() < additional {
+            self.start_segment(additional);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Lock backends for testing a `RawCandyCane` under contention.
+//!
+//! `RawCandyCane` is generic over its locks, so plugging in
+//! [`TestRwLock`] and [`TestMutex`] lets a test count lock
+//! acquisitions, record the order locks were taken in, and
+//! shake up thread interleavings by yielding or sleeping around
+//! every lock operation.
+//!
+//! Chaos is opt-in per thread through [`Chaos::enable`]. Each
+//! thread draws its pauses from its own [`Rng`], seeded from the
+//! test's seed and the thread's index, so rerunning a failing
+//! seed replays the same pauses on every thread. The OS still
+//! schedules the threads, so a replay makes a failure much more
+//! likely to reproduce rather than guaranteeing it.
+
+use parking_lot::lock_api::{RawMutex, RawRwLock};
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A tiny deterministic PRNG (SplitMix64), so that randomized
+/// tests don't need any extra dependencies.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`. `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Uniform in `0..=n`.
+    pub fn up_to(&mut self, n: usize) -> usize {
+        self.below(n + 1)
+    }
+
+    /// `true` roughly once every `n` calls.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LockKind {
+    Shared,
+    Exclusive,
+    Mutex,
+}
+
+/// One successful lock acquisition.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Acquisition {
+    /// The index the acquiring thread passed to [`Chaos::enable`].
+    pub thread: usize,
+    /// Unique per lock, handed out in the order locks are first used.
+    pub lock: usize,
+    pub kind: LockKind,
+    /// Whether this came from a `try_lock*` call.
+    pub tried: bool,
+}
+
+/// Collects the acquisitions made by every thread it is
+/// handed to, in the order they happened.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    log: Arc<Mutex<Vec<Acquisition>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acquisitions(&self) -> Vec<Acquisition> {
+        self.log.lock().clone()
+    }
+
+    pub fn count(&self, kind: LockKind) -> usize {
+        self.log.lock().iter().filter(|x| x.kind == kind).count()
+    }
+
+    pub fn clear(&self) {
+        self.log.lock().clear();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChaosConfig {
+    /// Yield roughly once every this many lock operations. 0 never yields.
+    pub yield_one_in: usize,
+    /// Sleep roughly once every this many lock operations. 0 never sleeps.
+    pub sleep_one_in: usize,
+    /// The longest a single injected sleep may last.
+    pub max_sleep: Duration,
+    pub recorder: Option<Recorder>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            yield_one_in: 4,
+            sleep_one_in: 32,
+            max_sleep: Duration::from_micros(50),
+            recorder: None,
+        }
+    }
+}
+
+struct ThreadChaos {
+    thread: usize,
+    rng: Rng,
+    config: ChaosConfig,
+}
+
+thread_local! {
+    static CHAOS: RefCell<Option<ThreadChaos>> = const { RefCell::new(None) };
+}
+
+/// Enables chaos on the current thread until dropped.
+pub struct Chaos {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Chaos {
+    /// `thread` picks this thread's stream of pauses, so each
+    /// thread taking part in a test should be given its own.
+    pub fn enable(seed: u64, thread: usize, config: ChaosConfig) -> Self {
+        let mut seeder = Rng::new(seed ^ (thread as u64).wrapping_mul(0xA24B_AED4_963E_E407));
+        let rng = Rng::new(seeder.next_u64());
+
+        CHAOS.with(|chaos| {
+            *chaos.borrow_mut() = Some(ThreadChaos { thread, rng, config });
+        });
+
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for Chaos {
+    fn drop(&mut self) {
+        CHAOS.with(|chaos| *chaos.borrow_mut() = None);
+    }
+}
+
+/// Maybe yields or sleeps, if chaos is enabled on this thread.
+///
+/// The test locks call this around every operation, but tests
+/// may also call it to widen windows in their own code.
+pub fn pause() {
+    let sleep = CHAOS.with(|chaos| {
+        let mut chaos = chaos.borrow_mut();
+        let chaos = chaos.as_mut()?;
+
+        let ThreadChaos { rng, config, .. } = chaos;
+        if config.sleep_one_in != 0 && rng.one_in(config.sleep_one_in) {
+            let max = config.max_sleep.as_nanos().max(1) as usize;
+            Some(Some(Duration::from_nanos(rng.below(max) as u64)))
+        } else if config.yield_one_in != 0 && rng.one_in(config.yield_one_in) {
+            Some(None)
+        } else {
+            None
+        }
+    });
+
+    match sleep {
+        Some(Some(duration)) => std::thread::sleep(duration),
+        Some(None) => std::thread::yield_now(),
+        None => {}
+    }
+}
+
+fn acquired(id: &LockId, kind: LockKind, tried: bool) {
+    CHAOS.with(|chaos| {
+        if let Some(ThreadChaos { thread, config: ChaosConfig { recorder: Some(recorder), .. }, .. }) = &*chaos.borrow() {
+            recorder.log.lock().push(Acquisition {
+                thread: *thread,
+                lock: id.get(),
+                kind,
+                tried,
+            });
+        }
+    });
+}
+
+static NEXT_LOCK_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Lazily assigned, so that locks can still be built in a `const`.
+struct LockId(AtomicUsize);
+
+impl LockId {
+    const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    fn get(&self) -> usize {
+        let id = self.0.load(Ordering::Relaxed);
+        if id != 0 {
+            return id;
+        }
+
+        let new = NEXT_LOCK_ID.fetch_add(1, Ordering::Relaxed);
+        match self.0.compare_exchange(0, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => new,
+            Err(existing) => existing,
+        }
+    }
+}
+
+/// A `parking_lot::RawRwLock` that pauses around every operation.
+pub struct TestRwLock {
+    inner: parking_lot::RawRwLock,
+    id: LockId,
+}
+
+unsafe impl RawRwLock for TestRwLock {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        inner: <parking_lot::RawRwLock as RawRwLock>::INIT,
+        id: LockId::new(),
+    };
+
+    type GuardMarker = <parking_lot::RawRwLock as RawRwLock>::GuardMarker;
+
+    fn lock_shared(&self) {
+        pause();
+        self.inner.lock_shared();
+        acquired(&self.id, LockKind::Shared, false);
+        pause();
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        pause();
+        let locked = self.inner.try_lock_shared();
+        if locked {
+            acquired(&self.id, LockKind::Shared, true);
+        }
+        pause();
+        locked
+    }
+
+    unsafe fn unlock_shared(&self) {
+        pause();
+        self.inner.unlock_shared();
+        pause();
+    }
+
+    fn lock_exclusive(&self) {
+        pause();
+        self.inner.lock_exclusive();
+        acquired(&self.id, LockKind::Exclusive, false);
+        pause();
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        pause();
+        let locked = self.inner.try_lock_exclusive();
+        if locked {
+            acquired(&self.id, LockKind::Exclusive, true);
+        }
+        pause();
+        locked
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        pause();
+        self.inner.unlock_exclusive();
+        pause();
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+}
+
+/// A `parking_lot::RawMutex` that pauses around every operation.
+pub struct TestMutex {
+    inner: parking_lot::RawMutex,
+    id: LockId,
+}
+
+unsafe impl RawMutex for TestMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        inner: <parking_lot::RawMutex as RawMutex>::INIT,
+        id: LockId::new(),
+    };
+
+    type GuardMarker = <parking_lot::RawMutex as RawMutex>::GuardMarker;
+
+    fn lock(&self) {
+        pause();
+        self.inner.lock();
+        acquired(&self.id, LockKind::Mutex, false);
+        pause();
+    }
+
+    fn try_lock(&self) -> bool {
+        pause();
+        let locked = self.inner.try_lock();
+        if locked {
+            acquired(&self.id, LockKind::Mutex, true);
+        }
+        pause();
+        locked
+    }
+
+    unsafe fn unlock(&self) {
+        pause();
+        self.inner.unlock();
+        pause();
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+}
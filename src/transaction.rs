@@ -0,0 +1,70 @@
+//! Editing a copy of a cane's elements, which only replaces them
+//! once it's committed.
+//!
+//! A write guard hands out the cane's own elements, so whatever was
+//! changed through it stays changed once it's dropped. A transaction
+//! clones them instead, and doesn't touch the cane until `commit`.
+//! Aborting then costs nothing but the copy, and leaves the cane
+//! exactly as it was, down to where each chunk's elements are.
+
+use crate::stats::Timer;
+use crate::slice_tracker::LockGuard;
+use crate::storage::sealed::Buffer;
+use crate::storage::{Contiguous, Storage};
+use crate::{into_cells, RawCandyCane};
+use parking_lot::lock_api::{RawMutex, RawRwLock};
+use std::ops::{Deref, DerefMut};
+
+/// Returned by [`RawCandyCane::write_transaction`], and derefs to
+/// a copy of the cane's elements. Dropping it aborts.
+pub struct CandyCaneTransaction<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage = Contiguous> {
+    _hold: Timer<'a>,
+    lock: LockGuard<'a, R>,
+    original: &'a RawCandyCane<R, M, T, SLICES, S>,
+    /// Dropped after `lock`, so that nobody waits for it.
+    vec: Vec<T>,
+}
+
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> CandyCaneTransaction<'a, R, M, T, SLICES, S> {
+    /// `lock` must be the exclusive lock of `original`, and `vec`
+    /// a copy of everything in it.
+    pub(crate) fn new(hold: Timer<'a>, lock: LockGuard<'a, R>, original: &'a RawCandyCane<R, M, T, SLICES, S>, vec: Vec<T>) -> Self {
+        Self {
+            _hold: hold,
+            lock,
+            original,
+            vec,
+        }
+    }
+
+    /// Replaces the cane's elements with the copy, and rebuilds
+    /// its chunks, like dropping a write guard does.
+    pub fn commit(self) {
+        let Self { _hold, lock, original, vec } = self;
+        let chunks = original.chunks_wanted(vec.len());
+
+        assert!(original.ensure_my_write_guard(&lock));
+        // SAFETY: `lock` is exclusive, and ours. The elements we
+        // replace are dropped along with the old buffer.
+        unsafe { *original.data.get() = S::Buffer::from_vec(into_cells(vec), chunks) };
+        original.reconstruct_chunks(&lock, chunks);
+    }
+
+    /// Throws the copy away, leaving the cane as it was. Same
+    /// as dropping the transaction.
+    pub fn abort(self) {}
+}
+
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> Deref for CandyCaneTransaction<'a, R, M, T, SLICES, S> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl<'a, R: RawRwLock, M: RawMutex, T, const SLICES: usize, S: Storage> DerefMut for CandyCaneTransaction<'a, R, M, T, SLICES, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.vec
+    }
+}
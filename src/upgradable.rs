@@ -0,0 +1,90 @@
+//! Reading a cane under a lock that can later be turned into
+//! the exclusive one.
+//!
+//! Checking the elements and then calling `write()` leaves a gap
+//! in which another writer can change them. The upgradable lock
+//! shares with readers, but only one thread can hold it, and no
+//! writer can take the exclusive lock while it's held, so none
+//! can get in between checking and upgrading.
+//!
+//! Anything other threads can do under a shared lock still can
+//! happen in between: mutable iterators, appends, and edits of
+//! `Segmented` canes.
+
+use crate::iter::streaming::{CandyCaneIterStreaming, IterState};
+use crate::iter::RangeError;
+use crate::raw::RawCandyCaneIterStreaming;
+use crate::slice_tracker::UpgradableGuard;
+use crate::storage::{Contiguous, Storage};
+use crate::{CandyCaneWriteGuard, RawCandyCane};
+use parking_lot::lock_api::{RawMutex, RawRwLockUpgrade};
+use std::ops::RangeBounds;
+use std::sync::atomic::Ordering;
+
+/// Returned by [`RawCandyCane::upgradable_read`]. Iterators over
+/// the cane have to come from here while it's held, since ones
+/// from the cane itself would wait behind writers waiting for it.
+pub struct CandyCaneUpgradableRead<'a, R: RawRwLockUpgrade, M: RawMutex, T, const SLICES: usize, S: Storage = Contiguous> {
+    lock: UpgradableGuard<'a, R>,
+    original: &'a RawCandyCane<R, M, T, SLICES, S>,
+}
+
+impl<'a, R: RawRwLockUpgrade, M: RawMutex, T, const SLICES: usize, S: Storage> CandyCaneUpgradableRead<'a, R, M, T, SLICES, S> {
+    /// `lock` must be the upgradable lock of `original`.
+    pub(crate) fn new(lock: UpgradableGuard<'a, R>, original: &'a RawCandyCane<R, M, T, SLICES, S>) -> Self {
+        Self { lock, original }
+    }
+
+    /// Like [`RawCandyCane::len`]. Elements can still be pushed,
+    /// inserted and removed by other threads, so this may change.
+    pub fn len(&self) -> usize {
+        self.original.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits for everyone else reading the cane to be done, and
+    /// hands out its elements like [`RawCandyCane::write`] does.
+    ///
+    /// Writers waiting for this lock keep waiting for the guard.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if this thread is holding any other iterator
+    /// or guard for this cane, since this would never return.
+    pub fn upgrade(self) -> CandyCaneWriteGuard<'a, R, M, T, SLICES, S> {
+        let Self { lock, original } = self;
+        original.upgrade_internal(lock)
+    }
+}
+
+impl<'a, R: RawRwLockUpgrade, M: RawMutex, T: Sync, const SLICES: usize, S: Storage> CandyCaneUpgradableRead<'a, R, M, T, SLICES, S> {
+    /// Like [`RawCandyCane::iter_streaming`], under this lock.
+    pub fn iter_streaming(&self, range: impl RangeBounds<usize>) -> CandyCaneIterStreaming<'_, T, R, M> {
+        self.try_iter_streaming(range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming(&self, range: impl RangeBounds<usize>) -> Result<CandyCaneIterStreaming<'_, T, R, M>, RangeError> {
+        // SAFETY: The iterator borrows `self`, which holds the
+        // upgradable lock until it's dropped or upgraded.
+        let internal = unsafe { RawCandyCaneIterStreaming::new_under(range, self.original, None) }?;
+        Ok(CandyCaneIterStreaming { inner: internal.read_only() })
+    }
+
+    /// Like [`RawCandyCane::iter_streaming_with`], under this lock.
+    pub fn iter_streaming_with<'s>(&'s self, state: &'s mut IterState, range: impl RangeBounds<usize>) -> CandyCaneIterStreaming<'s, T, R, M> {
+        self.try_iter_streaming_with(state, range).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_iter_streaming_with<'s>(
+        &'s self,
+        state: &'s mut IterState,
+        range: impl RangeBounds<usize>,
+    ) -> Result<CandyCaneIterStreaming<'s, T, R, M>, RangeError> {
+        // SAFETY: As above.
+        let internal = unsafe { RawCandyCaneIterStreaming::new_under(range, self.original, Some(state)) }?;
+        Ok(CandyCaneIterStreaming { inner: internal.read_only() })
+    }
+}
@@ -0,0 +1,81 @@
+//! What an iterator does once every chunk it has left is busy.
+
+use parking_lot::{Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Set with [`RawCandyCane::set_wait_strategy`].
+///
+/// [`RawCandyCane::set_wait_strategy`]: crate::RawCandyCane::set_wait_strategy
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WaitStrategy {
+    /// Blocks on the lock of one of the remaining chunks, even
+    /// if another one is released first. This is the default.
+    #[default]
+    Block,
+    /// Spins, backing off to yielding the thread, and looks
+    /// for a free chunk again after every round.
+    Spin,
+    /// Sleeps until any chunk of the cane is released, and then
+    /// looks for a free chunk again.
+    Park,
+}
+
+/// Counts chunk releases, so that parked iterators can
+/// wait for the next one.
+#[derive(Default)]
+pub(crate) struct Released {
+    generation: AtomicU64,
+    parked: AtomicUsize,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Released {
+    /// Must be read before looking for a free chunk, and
+    /// handed to `park` if none was found.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Must be called once a chunk's lock was released.
+    pub(crate) fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        // Either we see the parked thread here, or it sees the
+        // new generation before it goes to sleep.
+        if self.parked.load(Ordering::SeqCst) != 0 {
+            let _lock = self.lock.lock();
+            self.wakeup.notify_all();
+        }
+    }
+
+    /// Sleeps until a chunk is released after `seen`.
+    pub(crate) fn park(&self, seen: u64) {
+        self.parked.fetch_add(1, Ordering::SeqCst);
+        let mut lock = self.lock.lock();
+        while self.generation.load(Ordering::SeqCst) == seen {
+            self.wakeup.wait(&mut lock);
+        }
+        drop(lock);
+        self.parked.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Exponential backoff for `WaitStrategy::Spin`.
+#[derive(Default)]
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    /// Past this, spinning longer is unlikely to help.
+    const SPIN_LIMIT: u32 = 6;
+
+    pub(crate) fn snooze(&mut self) {
+        if self.step <= Self::SPIN_LIMIT {
+            (0..1 << self.step).for_each(|_| std::hint::spin_loop());
+            self.step += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
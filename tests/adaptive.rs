@@ -0,0 +1,159 @@
+use candy_cane::{Adaptive, RawCandyCane};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane<const SLICES: usize> = RawCandyCane<RawRwLock, RawMutex, usize, SLICES>;
+
+const THREADS: usize = 3;
+/// Long enough for every thread to get to the first chunk. Miri's
+/// clock goes by how much it has run, which takes it a lot longer.
+const HOLD: Duration = Duration::from_millis(if cfg!(miri) { 5000 } else { 50 });
+
+/// Holds the first chunk until every thread has had to block on it.
+fn contend<const SLICES: usize>(cane: &Cane<SLICES>) {
+    let started = Barrier::new(THREADS + 1);
+    std::thread::scope(|s| {
+        let mut held = cane.iter_streaming_mut(..1);
+        *held.next().unwrap() += 1;
+
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                started.wait();
+                let mut iter = cane.iter_streaming_mut(..);
+                while let Some(x) = iter.next() {
+                    *x += 1;
+                }
+            });
+        }
+
+        started.wait();
+        std::thread::sleep(HOLD);
+    });
+}
+
+#[test]
+fn splits_under_contention() {
+    let cane = Cane::<1>::from_vec(vec![0; 64]);
+    cane.set_adaptive(Some(Adaptive {
+        window: 4,
+        ..Adaptive::default()
+    }));
+
+    for expected in [2, 4, 8] {
+        contend(&cane);
+        cane.rebalance();
+        assert_eq!(cane.chunk_count(), expected);
+    }
+
+    // Every element was still visited once per pass.
+    let mut expected = vec![THREADS * 3; 64];
+    expected[0] += 3;
+    assert_eq!(cane.into_inner(), expected);
+}
+
+#[test]
+fn merges_when_uncontended() {
+    let cane = Cane::<16>::from_vec((0..64).collect());
+    cane.set_adaptive(Some(Adaptive {
+        window: 2,
+        min_chunks: 2,
+        merge_below: Duration::from_secs(1),
+        ..Adaptive::default()
+    }));
+
+    for expected in [8, 4, 2, 2] {
+        let mut iter = cane.iter_streaming(..);
+        let mut sum = 0;
+        while let Some(x) = iter.next() {
+            sum += *x;
+        }
+        drop(iter);
+        assert_eq!(sum, 63 * 64 / 2);
+
+        cane.rebalance();
+        assert_eq!(cane.chunk_count(), expected);
+    }
+
+    let mut iter = cane.iter_streaming(10..50);
+    let mut found = Vec::new();
+    while let Some(&x) = iter.next() {
+        found.push(x);
+    }
+    found.sort_unstable();
+    assert_eq!(found, (10..50).collect::<Vec<_>>());
+}
+
+#[test]
+fn waits_for_a_full_window() {
+    let cane = Cane::<16>::from_vec((0..64).collect());
+    cane.set_adaptive(Some(Adaptive {
+        window: 1000,
+        merge_below: Duration::from_secs(1),
+        ..Adaptive::default()
+    }));
+
+    let mut iter = cane.iter_streaming(..);
+    while iter.next().is_some() {}
+    drop(iter);
+
+    cane.rebalance();
+    assert_eq!(cane.chunk_count(), 16);
+}
+
+#[test]
+fn disabling_goes_back_to_slices() {
+    let cane = Cane::<16>::from_vec((0..64).collect());
+    cane.set_adaptive(Some(Adaptive {
+        window: 1,
+        merge_below: Duration::from_secs(1),
+        ..Adaptive::default()
+    }));
+
+    let mut iter = cane.iter_streaming(..);
+    while iter.next().is_some() {}
+    drop(iter);
+    cane.rebalance();
+    assert_eq!(cane.chunk_count(), 8);
+
+    cane.set_adaptive(None);
+    assert_eq!(cane.chunk_count(), 16);
+}
+
+#[test]
+fn adapts_without_writes() {
+    let cane = Cane::<16>::from_vec((0..64).collect());
+    cane.set_adaptive(Some(Adaptive {
+        window: 2,
+        merge_below: Duration::from_secs(1),
+        ..Adaptive::default()
+    }));
+
+    for expected in [8, 4] {
+        let mut iter = cane.iter_streaming(..);
+        while iter.next().is_some() {}
+        drop(iter);
+        assert_eq!(cane.chunk_count(), expected);
+    }
+}
+
+#[test]
+fn iterators_dont_adapt_while_others_hold_the_cane() {
+    let cane = Cane::<16>::from_vec((0..64).collect());
+    cane.set_adaptive(Some(Adaptive {
+        window: 2,
+        merge_below: Duration::from_secs(1),
+        ..Adaptive::default()
+    }));
+
+    let mut held = cane.iter_streaming(..1);
+    held.next().unwrap();
+    let mut iter = cane.iter_streaming(4..);
+    while iter.next().is_some() {}
+    drop(iter);
+    assert_eq!(cane.chunk_count(), 16);
+
+    // Whoever next finds the cane to themselves does it instead.
+    drop(held);
+    assert_eq!(cane.chunk_count(), 8);
+}
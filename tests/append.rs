@@ -0,0 +1,107 @@
+use crate::common::visited;
+use candy_cane::RawCandyCane;
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::Arc;
+
+type Cane<T> = RawCandyCane<RawRwLock, RawMutex, T, 4>;
+
+fn with_capacity(len: usize, capacity: usize) -> Cane<usize> {
+    let mut data = Vec::with_capacity(capacity);
+    data.extend(0..len);
+    Cane::from_vec(data)
+}
+
+#[test]
+fn push_into_spare_capacity() {
+    let cane = with_capacity(8, 16);
+
+    let mut running = cane.iter_streaming_mut(..);
+    running.next();
+    // Would wait for `running` forever if it needed `write()`.
+    cane.push(8);
+    cane.extend(9..12);
+
+    let mut seen = 1;
+    while running.next().is_some() {
+        seen += 1;
+    }
+    assert_eq!(seen, 8);
+    drop(running);
+
+    assert_eq!(cane.len(), 12);
+    assert_eq!(visited(&cane), (0..12).collect::<Vec<_>>());
+}
+
+#[test]
+fn grows_past_capacity() {
+    let cane = with_capacity(2, 4);
+    cane.extend(2..7);
+    assert_eq!(cane.len(), 7);
+    assert_eq!(*cane.write(), [0, 1, 2, 3, 4, 5, 6]);
+
+    let cane = Cane::new();
+    cane.push(0);
+    cane.reserve(10);
+    cane.extend(1..11);
+    assert_eq!(cane.into_inner(), (0..11).collect::<Vec<_>>());
+}
+
+#[test]
+fn appended_elements_can_be_written() {
+    let cane = with_capacity(8, 16);
+    cane.extend(8..12);
+
+    let mut iter = cane.iter_streaming_mut(10..);
+    while let Some(x) = iter.next() {
+        *x *= 10;
+    }
+    drop(iter);
+    cane.write_range(6..9).iter_mut().for_each(|x| *x += 1);
+
+    let mut cane = cane;
+    assert_eq!(cane.as_mut_slice(), [0, 1, 2, 3, 4, 5, 7, 8, 9, 9, 100, 110]);
+}
+
+#[test]
+fn appended_elements_are_dropped() {
+    let counted = Arc::new(());
+    let cane = Cane::from_vec(Vec::with_capacity(8));
+    cane.reserve(8);
+    (0..6).for_each(|_| cane.push(counted.clone()));
+    assert_eq!(Arc::strong_count(&counted), 7);
+    drop(cane);
+    assert_eq!(Arc::strong_count(&counted), 1);
+}
+
+#[test]
+fn producers_and_iterators() {
+    const PER_PRODUCER: usize = if cfg!(miri) { 20 } else { 2000 };
+    let cane = with_capacity(0, 0);
+    cane.reserve(4 * PER_PRODUCER);
+
+    std::thread::scope(|s| {
+        for producer in 0..4 {
+            let cane = &cane;
+            s.spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    cane.push(producer * PER_PRODUCER + i);
+                }
+            });
+        }
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                let mut last = 0;
+                while last < 4 * PER_PRODUCER {
+                    let visited = visited(&cane);
+                    // Every element at most once, and never fewer than before.
+                    visited.windows(2).for_each(|x| assert!(x[0] < x[1]));
+                    assert!(visited.len() >= last);
+                    last = visited.len();
+                }
+            });
+        }
+    });
+
+    assert_eq!(visited(&cane), (0..4 * PER_PRODUCER).collect::<Vec<_>>());
+}
@@ -0,0 +1,27 @@
+//! Helpers used by more than one test module.
+
+use candy_cane::{RawCandyCane, Storage};
+use hushed_panic::hush_this_test;
+use parking_lot::lock_api::{RawMutex, RawRwLock};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Everything an iterator over all of `cane` visits, sorted.
+pub fn visited<R: RawRwLock, M: RawMutex, const SLICES: usize, S: Storage>(cane: &RawCandyCane<R, M, usize, SLICES, S>) -> Vec<usize> {
+    let mut iter = cane.iter_streaming(..);
+    let mut visited = Vec::new();
+    while let Some(&x) = iter.next() {
+        visited.push(x);
+    }
+    visited.sort_unstable();
+    visited
+}
+
+/// What `f` panicked with. Panics if it didn't.
+pub fn panic_message(f: impl FnOnce()) -> String {
+    let _x = hush_this_test();
+    let payload = catch_unwind(AssertUnwindSafe(f)).expect_err("should have panicked");
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+    }
+}
@@ -0,0 +1,241 @@
+//! Self-deadlocks only panic in debug builds, so these
+//! would hang in release builds instead.
+
+use crate::common::panic_message;
+use candy_cane::{RawCandyCane, RcuCandyCane, WaitStrategy};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane<const SLICES: usize> = RawCandyCane<RawRwLock, RawMutex, usize, SLICES>;
+
+#[test]
+fn write_while_iterating() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let mut iter = cane.iter_streaming(..2);
+        iter.next();
+        cane.write();
+    });
+    assert!(message.starts_with("self-deadlock: called `write()`"), "{}", message);
+    assert!(message.contains("a shared lock (from a streaming iterator) and the lock on chunk 0"), "{}", message);
+
+    // Unwinding dropped the iterator, so nothing is held anymore.
+    cane.write().push(5);
+    assert_eq!(cane.len(), 5);
+}
+
+#[test]
+fn write_twice() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let _guard = cane.write();
+        cane.write();
+    });
+    assert!(message.contains("the exclusive lock (from a write guard)"), "{}", message);
+}
+
+#[test]
+fn read_while_writing() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let _guard = cane.write();
+        cane.len();
+    });
+    assert!(message.starts_with("self-deadlock: tried to take a shared lock"), "{}", message);
+}
+
+#[test]
+fn nested_iterators_over_the_same_chunk() {
+    let cane = Cane::<1>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let mut outer = cane.iter_streaming_mut(..);
+        outer.next();
+        let mut inner = cane.iter_streaming_mut(..);
+        inner.next();
+    });
+    assert!(message.contains("waiting for the lock on chunk 0"), "{}", message);
+}
+
+#[test]
+fn nested_iterators_over_a_chunk_worth_stealing_from() {
+    // Long enough for `outer` to leave most of it to thieves.
+    let cane = Cane::<1>::from_vec((0..1000).collect());
+
+    let message = panic_message(|| {
+        let mut outer = cane.iter_streaming_mut(..);
+        outer.next();
+        let mut inner = cane.iter_streaming_mut(..);
+        inner.next();
+        while outer.next().is_some() {}
+    });
+    assert!(message.contains("waiting for the lock on chunk 0"), "{}", message);
+}
+
+#[test]
+fn stealing_from_a_thief_on_the_same_thread() {
+    let cane = Cane::<1>::from_vec((0..1000).collect());
+    let (owned, visited) = (Barrier::new(2), AtomicUsize::new(0));
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut owner = cane.iter_streaming(..);
+            owner.next();
+            owned.wait();
+            owned.wait();
+            while owner.next().is_some() {
+                visited.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        owned.wait();
+        let message = panic_message(|| {
+            // Takes 562.., the back half of what the owner has left.
+            let mut first = cane.iter_streaming(..);
+            first.next();
+            owned.wait();
+            // Once the owner has taken the rest of its half, only
+            // what `first` has left can be stolen.
+            while visited.load(Ordering::SeqCst) < 561 {
+                std::thread::yield_now();
+            }
+            let mut second = cane.iter_streaming(..);
+            second.next();
+            while first.next().is_some() {}
+        });
+        assert!(message.starts_with("self-deadlock: waiting for part of chunk 0"), "{}", message);
+    });
+}
+
+#[test]
+fn nested_iterators_spinning_or_parking() {
+    for wait in [WaitStrategy::Spin, WaitStrategy::Park] {
+        let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+        cane.set_wait_strategy(wait);
+
+        let message = panic_message(|| {
+            let mut outer = cane.iter_streaming_mut(2..);
+            outer.next();
+            let mut inner = cane.iter_streaming_mut(..);
+            while inner.next().is_some() {}
+        });
+        assert!(message.contains("waiting for the lock on chunk 1"), "{:?}: {}", wait, message);
+    }
+}
+
+#[test]
+fn nested_iterators_over_different_chunks() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let mut outer = cane.iter_streaming_mut(..2);
+    while let Some(x) = outer.next() {
+        let mut inner = cane.iter_streaming(2..);
+        while let Some(y) = inner.next() {
+            *x += *y;
+        }
+    }
+    drop(outer);
+
+    assert_eq!(cane.into_inner(), vec![8, 9, 3, 4]);
+}
+
+#[test]
+fn nested_read_with_a_waiting_writer() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    std::thread::scope(|s| {
+        let message = panic_message(|| {
+            let _outer = cane.iter_streaming(..);
+            s.spawn(|| cane.write().push(5));
+            // Give the writer time to start waiting on `_outer`.
+            std::thread::sleep(Duration::from_millis(50));
+            cane.iter_streaming(..);
+        });
+        assert!(message.contains("while a writer is waiting"), "{}", message);
+    });
+
+    assert_eq!(cane.len(), 5);
+}
+
+#[test]
+fn write_range_while_iterating() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let mut iter = cane.iter_streaming(..2);
+        iter.next();
+        // Chunk 1 is free, so only the second one panics.
+        drop(cane.write_range(2..));
+        cane.write_range(..);
+    });
+    assert!(message.contains("waiting for the lock on chunk 0"), "{}", message);
+
+    let _guard = cane.write_range(..);
+}
+
+#[test]
+fn upgrade_while_iterating() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let read = cane.upgradable_read();
+        let _iter = cane.iter_streaming(..);
+        read.upgrade();
+    });
+    assert!(message.starts_with("self-deadlock: called `upgrade()`"), "{}", message);
+    assert!(message.ends_with("holds a shared lock (from a streaming iterator) on it"), "{}", message);
+
+    cane.upgradable_read().upgrade().push(5);
+    assert_eq!(cane.len(), 5);
+}
+
+#[test]
+fn upgradable_read_twice() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let _read = cane.upgradable_read();
+        cane.upgradable_read();
+    });
+    assert!(message.contains("the upgradable lock (from an upgradable read)"), "{}", message);
+}
+
+#[test]
+fn read_under_an_upgradable_read_with_a_waiting_writer() {
+    let cane = Cane::<2>::from_vec(vec![1, 2, 3, 4]);
+
+    std::thread::scope(|s| {
+        let message = panic_message(|| {
+            let _read = cane.upgradable_read();
+            s.spawn(|| cane.write().push(5));
+            std::thread::sleep(Duration::from_millis(50));
+            cane.len();
+        });
+        assert!(message.contains("while a writer is waiting"), "{}", message);
+        assert!(message.ends_with("release the upgradable lock (from an upgradable read)"), "{}", message);
+    });
+
+    assert_eq!(cane.len(), 5);
+}
+
+#[test]
+fn rcu_write_twice() {
+    let cane = RcuCandyCane::<usize, 2>::from_vec(vec![1, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let mut guard = cane.write();
+        guard.push(5);
+        // Reading never waits, even under a write guard.
+        assert_eq!(cane.len(), 4);
+        cane.write();
+    });
+    assert!(message.starts_with("self-deadlock: called `write()`"), "{}", message);
+
+    // Unwinding published what was pushed.
+    assert_eq!(cane.len(), 5);
+}
@@ -0,0 +1,210 @@
+use crate::common::visited;
+use candy_cane::testing::Rng;
+use candy_cane::{ChunkBounds, RawCandyCane, Segmented};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane<T> = RawCandyCane<RawRwLock, RawMutex, T, 4, Segmented>;
+
+#[test]
+fn like_a_vec() {
+    let cane = Cane::from_vec((0..12).collect());
+    let mut model = (0..12).collect::<Vec<_>>();
+
+    for (index, value) in [(0, 100), (12, 101), (5, 102), (14, 103), (3, 104)] {
+        cane.insert(index, value);
+        model.insert(index, value);
+    }
+    for index in [0, 9, 14, 4] {
+        assert_eq!(cane.remove(index), model.remove(index));
+    }
+
+    assert_eq!(cane.len(), model.len());
+    assert_eq!(cane.into_inner(), model);
+}
+
+#[test]
+fn swap_remove_stays_in_its_chunk() {
+    let cane = Cane::from_vec((0..12).collect());
+    // The first chunk is `0..3`.
+    assert_eq!(cane.swap_remove(0), 0);
+    assert_eq!(cane.into_inner(), [2, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+}
+
+#[test]
+fn edits_dont_wait_for_other_chunks() {
+    let cane = Cane::from_vec((0..12).collect());
+
+    let mut running = cane.iter_streaming_mut(..3);
+    running.next();
+    // Would wait for `running` forever if they needed `write()`.
+    cane.insert(6, 100);
+    assert_eq!(cane.remove(12), 11);
+    cane.push(101);
+    assert_eq!(cane.swap_remove(12), 101);
+
+    while running.next().is_some() {}
+    drop(running);
+    assert_eq!(cane.into_inner(), [0, 1, 2, 3, 4, 5, 100, 6, 7, 8, 9, 10]);
+}
+
+#[test]
+fn appended_elements_can_be_edited() {
+    let cane = Cane::new();
+    cane.reserve(8);
+    cane.extend(0..6);
+    cane.insert(6, 6);
+    cane.insert(0, 100);
+    assert_eq!(cane.remove(3), 2);
+    cane.push(7);
+    assert_eq!(cane.into_inner(), [100, 0, 1, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn split_and_merge() {
+    let cane = Cane::from_vec((0..16).collect());
+    cane.set_chunk_bounds(ChunkBounds {
+        split_above: 8,
+        merge_below: 2,
+    });
+    let mut model = (0..16).collect::<Vec<_>>();
+
+    // The first chunk grows from four to nine.
+    for value in 100..105 {
+        cane.insert(1, value);
+        model.insert(1, value);
+    }
+    assert_eq!(cane.chunk_count(), 5);
+
+    // And the last one shrinks away.
+    for _ in 0..4 {
+        assert_eq!(cane.remove(cane.len() - 1), model.pop().unwrap());
+    }
+    assert_eq!(cane.chunk_count(), 4);
+
+    assert_eq!(visited(&cane), {
+        let mut sorted = model.clone();
+        sorted.sort_unstable();
+        sorted
+    });
+    assert_eq!(cane.into_inner(), model);
+}
+
+#[test]
+fn waits_for_iterators_to_come_back() {
+    const LEN: usize = if cfg!(miri) { 80 } else { 1000 };
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 1, Segmented>::from_vec((0..LEN).collect());
+    let stolen = Barrier::new(2);
+
+    let mut owner = cane.iter_streaming(..);
+    owner.next();
+
+    std::thread::scope(|s| {
+        let thief = s.spawn(|| {
+            // With the only chunk taken, this borrows the back half
+            // of what `owner` has left, and has to come back for
+            // the front once `owner` is done.
+            let mut iter = cane.iter_streaming(..);
+            let mut visited = vec![*iter.next().unwrap()];
+            stolen.wait();
+            // For the editor to be waiting for the chunk first.
+            stolen.wait();
+            while let Some(&x) = iter.next() {
+                visited.push(x);
+            }
+            visited.sort_unstable();
+            visited
+        });
+
+        stolen.wait();
+        // Moving the front along by one would make the thief visit
+        // the new element, and miss the last one of the front.
+        let editor = s.spawn(|| cane.insert(0, LEN));
+        std::thread::sleep(Duration::from_millis(50));
+        stolen.wait();
+        std::thread::sleep(Duration::from_millis(50));
+        drop(owner);
+
+        editor.join().unwrap();
+        assert_eq!(thief.join().unwrap(), (0..LEN).collect::<Vec<_>>());
+    });
+
+    let mut contents = cane.into_inner();
+    contents.sort_unstable();
+    assert_eq!(contents, (0..=LEN).collect::<Vec<_>>());
+}
+
+#[test]
+fn editors_and_iterators() {
+    const EDITS: usize = if cfg!(miri) { 20 } else { 2000 };
+    let cane = Cane::from_vec((0..64).collect());
+    cane.set_chunk_bounds(ChunkBounds {
+        split_above: 32,
+        merge_below: 4,
+    });
+    let next = AtomicUsize::new(64);
+    let done = AtomicBool::new(false);
+
+    // Each editor removes at most one element after seeing more than
+    // this, so there never are fewer, however long one is held up.
+    const FLOOR: usize = 48;
+    std::thread::scope(|s| {
+        let editors = (0..2)
+            .map(|seed| {
+                let (cane, next) = (&cane, &next);
+                s.spawn(move || {
+                    let mut rng = Rng::new(seed);
+                    for _ in 0..EDITS {
+                        if rng.one_in(2) || cane.len() < FLOOR + 2 {
+                            cane.insert(rng.up_to(FLOOR), next.fetch_add(1, Ordering::Relaxed));
+                        } else {
+                            cane.remove(rng.below(FLOOR));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    // Every element at most once.
+                    visited(&cane).windows(2).for_each(|x| assert!(x[0] < x[1]));
+                }
+            });
+        }
+
+        let joined = editors.into_iter().map(|x| x.join()).collect::<Vec<_>>();
+        // Before panicking, or the readers never stop.
+        done.store(true, Ordering::Relaxed);
+        joined.into_iter().for_each(|x| x.unwrap());
+    });
+
+    let mut contents = cane.into_inner();
+    let len = contents.len();
+    contents.sort_unstable();
+    contents.dedup();
+    assert_eq!(contents.len(), len);
+}
+
+#[test]
+fn edits_can_move_chunks_out_of_a_range() {
+    let cane = Cane::from_vec((0..16).collect());
+
+    // Wants the second and third chunks, and takes one of them.
+    let mut iter = cane.iter_streaming(4..9);
+    let mut visited = vec![*iter.next().unwrap()];
+    // Moves the third one past the end of the range.
+    cane.insert(0, 100);
+    cane.insert(0, 101);
+    while let Some(&x) = iter.next() {
+        visited.push(x);
+    }
+    drop(iter);
+
+    visited.sort_unstable();
+    visited.windows(2).for_each(|x| assert!(x[0] < x[1]));
+    assert!(visited.iter().all(|x| (4..9).contains(x)), "{:?}", visited);
+}
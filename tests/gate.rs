@@ -0,0 +1,33 @@
+use crate::common::visited;
+use candy_cane::CandyCane;
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+fn reads_after_a_write() {
+    let cane = CandyCane::<usize>::new();
+    cane.write().extend(0..12);
+
+    assert!(visited(&cane).into_iter().eq(0..12));
+    assert_eq!(cane.len(), 12);
+}
+
+#[test]
+fn readers_wait_for_a_writer_then_carry_on() {
+    let cane = CandyCane::<usize>::new();
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        let mut guard = cane.write();
+        s.spawn(|| {
+            assert_eq!(cane.len(), 1);
+            tx.send(()).unwrap();
+        });
+
+        // Still held, so the reader can't have got through.
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        guard.push(1);
+        drop(guard);
+        rx.recv().unwrap();
+    });
+}
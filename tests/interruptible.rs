@@ -0,0 +1,177 @@
+use candy_cane::{OnLayoutChange, RawCandyCane, RoundRobin};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 4>;
+
+/// Visits the first chunk of a fresh cane of 16, then lets `write`
+/// run once it's waiting, and visits the rest.
+fn write_after_first_chunk(on_change: OnLayoutChange, write: impl FnOnce(&Cane) + Send) -> (Vec<usize>, bool, Cane) {
+    let cane = Cane::from_vec((0..16).collect());
+    cane.set_chunk_policy(RoundRobin::default());
+    let started = Barrier::new(2);
+
+    let (visited, changed) = std::thread::scope(|s| {
+        s.spawn(|| {
+            started.wait();
+            write(&cane);
+        });
+
+        // Round robin starts its first iterator from the first chunk.
+        let mut iter = cane.iter_streaming_interruptible(.., on_change);
+        let mut visited = (0..4).map(|_| *iter.next().unwrap()).collect::<Vec<_>>();
+        started.wait();
+        // Give the writer time to start waiting.
+        std::thread::sleep(Duration::from_millis(50));
+        while let Some(&x) = iter.next() {
+            visited.push(x);
+        }
+        // A new plan starts wherever the policy says.
+        visited[4..].sort_unstable();
+        (visited, iter.layout_changed())
+    });
+    (visited, changed, cane)
+}
+
+#[test]
+fn writing_values_keeps_going() {
+    let (visited, changed, cane) = write_after_first_chunk(OnLayoutChange::Stop, |cane| cane.write()[15] = 100);
+    assert!(!changed);
+    assert_eq!(visited, (0..15).chain([100]).collect::<Vec<_>>());
+    assert_eq!(cane.chunk_count(), 4);
+}
+
+#[test]
+fn replanning_visits_whats_left() {
+    let (visited, changed, cane) = write_after_first_chunk(OnLayoutChange::Replan, |cane| cane.write().push(16));
+    assert!(changed);
+    // Not the pushed element, which wasn't there when it started.
+    assert_eq!(visited, (0..16).collect::<Vec<_>>());
+    assert_eq!(cane.len(), 17);
+}
+
+#[test]
+fn stopping_when_the_chunks_change() {
+    let (visited, changed, _) = write_after_first_chunk(OnLayoutChange::Stop, |cane| cane.write().push(16));
+    assert!(changed);
+    assert_eq!(visited, [0, 1, 2, 3]);
+}
+
+#[test]
+fn replanning_drops_whats_gone() {
+    let (visited, changed, _) = write_after_first_chunk(OnLayoutChange::Replan, |cane| cane.write().truncate(10));
+    assert!(changed);
+    assert_eq!(visited, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn writers_dont_wait_for_the_end() {
+    let cane = Cane::from_vec(vec![0; 64]);
+    let written = AtomicBool::new(false);
+    let started = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming_mut_interruptible(.., OnLayoutChange::Stop);
+            *iter.next().unwrap() += 1;
+            started.wait();
+            while let Some(x) = iter.next() {
+                *x += 1;
+                if !written.load(Ordering::Relaxed) {
+                    // Give the writer time to start waiting.
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+            // Any other iterator would still have had it waiting.
+            assert!(written.load(Ordering::Relaxed));
+            assert!(!iter.layout_changed());
+        });
+
+        started.wait();
+        let mut guard = cane.write();
+        guard.iter_mut().for_each(|x| *x += 10);
+        // While the iterator's still waiting for us.
+        written.store(true, Ordering::Relaxed);
+        drop(guard);
+    });
+
+    assert_eq!(cane.into_inner(), vec![11; 64]);
+}
+
+#[test]
+fn iterators_and_value_writers() {
+    const ROUNDS: usize = if cfg!(miri) { 5 } else { 200 };
+    let cane = Cane::from_vec(vec![0; 256]);
+    let writes = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                for _ in 0..ROUNDS {
+                    let mut iter = cane.iter_streaming_mut_interruptible(.., OnLayoutChange::Stop);
+                    while let Some(x) = iter.next() {
+                        *x += 1;
+                    }
+                    // Only ever stops at the end.
+                    assert!(!iter.layout_changed());
+                }
+                done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        s.spawn(|| {
+            while done.load(Ordering::Relaxed) < 2 {
+                cane.write().iter_mut().for_each(|x| *x += 1000);
+                writes.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    });
+
+    // Every iterator visited every element exactly once.
+    let expected = 2 * ROUNDS + 1000 * writes.into_inner();
+    assert_eq!(cane.into_inner(), vec![expected; 256]);
+}
+
+#[test]
+fn iterators_and_rebuilding_writers() {
+    const PUSHES: usize = if cfg!(miri) { 40 } else { 4000 };
+    let cane = Cane::from_vec((0..256).collect());
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let len = cane.len();
+                    let mut iter = cane.iter_streaming_interruptible(.., OnLayoutChange::Replan);
+                    let mut visited = Vec::new();
+                    while let Some(&x) = iter.next() {
+                        visited.push(x);
+                    }
+                    drop(iter);
+
+                    // Appending never moves anything, so what's left
+                    // to visit after a rebuild is right where it was.
+                    visited.sort_unstable();
+                    assert!(visited.len() >= len);
+                    assert_eq!(visited, (0..visited.len()).collect::<Vec<_>>());
+                }
+            });
+        }
+
+        s.spawn(|| {
+            for next in 256..256 + PUSHES {
+                if next % 16 == 0 {
+                    // Rebuilds the chunks.
+                    cane.write().push(next);
+                } else {
+                    cane.push(next);
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+    });
+}
@@ -1,3 +1,24 @@
-extern crate test;
-
+mod adaptive;
+mod append;
+mod common;
+#[cfg(debug_assertions)]
+mod deadlock;
+mod edit;
+mod gate;
+mod interruptible;
+mod model;
+mod optimistic;
+mod policy;
 mod publicity;
+mod ranges;
+mod rcu;
+#[cfg(feature = "stats")]
+mod stats;
+mod steal;
+mod storage;
+mod state;
+mod stress;
+mod transaction;
+mod upgradable;
+mod wait;
+mod write_range;
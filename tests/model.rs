@@ -0,0 +1,298 @@
+//! Runs random sequences of operations against both a
+//! `RawCandyCane` and a plain `Vec`, and checks that they
+//! always agree.
+
+use candy_cane::testing::Rng;
+use candy_cane::{Adaptive, ChunkBounds, Contiguous, RawCandyCane, Segmented, Storage};
+use parking_lot::{RawMutex, RawRwLock};
+use std::ops::Bound;
+
+type Bounds = (Bound<usize>, Bound<usize>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Item {
+    id: u64,
+    value: u64,
+    visits: u32,
+    /// Something with a destructor, so that double drops
+    /// and leaks through the write guard get noticed.
+    tag: Box<u64>,
+}
+
+impl Item {
+    fn new(id: u64, value: u64) -> Self {
+        Self {
+            id,
+            value,
+            visits: 0,
+            tag: Box::new(id),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Push(u64),
+    Truncate(usize),
+    Insert(usize, u64),
+    Remove(usize),
+    Clear,
+    Read(Bounds),
+    Write(Bounds, u64),
+}
+
+fn random_bound(rng: &mut Rng, len: usize) -> Bound<usize> {
+    // Mostly in bounds, but sometimes just past the end.
+    let at = rng.up_to(len + 1);
+    match rng.below(3) {
+        0 => Bound::Included(at),
+        1 => Bound::Excluded(at),
+        _ => Bound::Unbounded,
+    }
+}
+
+fn random_op(rng: &mut Rng, len: usize) -> Op {
+    match rng.below(20) {
+        0..=4 => Op::Push(rng.next_u64()),
+        5 => Op::Truncate(rng.up_to(len)),
+        6 => Op::Insert(rng.up_to(len), rng.next_u64()),
+        7 if len > 0 => Op::Remove(rng.below(len)),
+        7 => Op::Insert(0, rng.next_u64()),
+        8 if rng.one_in(4) => Op::Clear,
+        8..=13 => Op::Read((random_bound(rng, len), random_bound(rng, len))),
+        _ => Op::Write((random_bound(rng, len), random_bound(rng, len)), rng.next_u64()),
+    }
+}
+
+/// How each storage inserts and removes elements: segmented canes
+/// one chunk at a time, and contiguous ones through `write()`.
+trait Edits: Storage + Sized {
+    /// Splits and merges chunks as often as possible.
+    fn setup<const SLICES: usize>(_cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>) {}
+    fn insert<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize, item: Item);
+    fn remove<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize) -> Item;
+}
+
+impl Edits for Contiguous {
+    fn insert<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize, item: Item) {
+        cane.write().insert(index, item);
+    }
+
+    fn remove<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize) -> Item {
+        cane.write().remove(index)
+    }
+}
+
+impl Edits for Segmented {
+    fn setup<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>) {
+        cane.set_chunk_bounds(ChunkBounds {
+            split_above: 4,
+            merge_below: 2,
+        });
+    }
+
+    fn insert<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize, item: Item) {
+        cane.insert(index, item);
+    }
+
+    fn remove<const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, Item, SLICES, Self>, index: usize) -> Item {
+        cane.remove(index)
+    }
+}
+
+struct Harness<const SLICES: usize, S: Edits> {
+    cane: RawCandyCane<RawRwLock, RawMutex, Item, SLICES, S>,
+    model: Vec<Item>,
+    next_id: u64,
+}
+
+impl<const SLICES: usize, S: Edits> Harness<SLICES, S> {
+    /// An adaptive cane keeps changing how many chunks it has
+    /// as it is written to.
+    fn new(initial: usize, adaptive: bool) -> Self {
+        let model = (0..initial as u64).map(|id| Item::new(id, id)).collect::<Vec<_>>();
+
+        let cane = RawCandyCane::from_vec(model.clone());
+        S::setup(&cane);
+        if adaptive {
+            cane.set_adaptive(Some(Adaptive {
+                window: 4,
+                ..Adaptive::default()
+            }));
+        }
+
+        Self {
+            cane,
+            model,
+            next_id: initial as u64,
+        }
+    }
+
+    fn fresh(&mut self, value: u64) -> Item {
+        self.next_id += 1;
+        Item::new(self.next_id, value)
+    }
+
+    fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Push(value) => {
+                let item = self.fresh(value);
+                self.model.push(item.clone());
+                self.cane.write().push(item);
+            }
+            Op::Truncate(len) => {
+                self.model.truncate(len);
+                self.cane.write().truncate(len);
+            }
+            Op::Insert(index, value) => {
+                let item = self.fresh(value);
+                self.model.insert(index, item.clone());
+                S::insert(&self.cane, index, item);
+            }
+            Op::Remove(index) => {
+                assert_eq!(S::remove(&self.cane, index), self.model.remove(index));
+            }
+            Op::Clear => {
+                self.model.clear();
+                self.cane.write().clear();
+            }
+            Op::Read(bounds) => {
+                let found = self.cane.try_iter_streaming(bounds).ok().map(|mut iter| {
+                    let mut found = Vec::new();
+                    while let Some(item) = iter.next() {
+                        found.push(item.clone());
+                    }
+                    found
+                });
+
+                let expected = self.model.get(bounds).map(<[Item]>::to_vec);
+                assert_eq!(sorted(expected), sorted(found));
+            }
+            Op::Write(bounds, delta) => {
+                let visited = self.cane.try_iter_streaming_mut(bounds).ok().map(|mut iter| {
+                    let mut visited = 0;
+                    while let Some(item) = iter.next() {
+                        item.value = item.value.wrapping_add(delta);
+                        item.visits += 1;
+                        visited += 1;
+                    }
+                    visited
+                });
+
+                let expected = self.model.get_mut(bounds).map(|items| {
+                    items.iter_mut().for_each(|item| {
+                        item.value = item.value.wrapping_add(delta);
+                        item.visits += 1;
+                    });
+                    items.len()
+                });
+
+                assert_eq!(expected, visited);
+            }
+        }
+    }
+
+    /// Reads everything back, which catches elements that
+    /// were visited twice or missed by the last operation.
+    fn check_contents(&self) {
+        let mut iter = self.cane.iter_streaming(..);
+        let mut found = Vec::new();
+        while let Some(item) = iter.next() {
+            found.push(item.clone());
+        }
+
+        assert_eq!(sorted(Some(self.model.clone())), sorted(Some(found)));
+        assert_eq!(self.model.len(), self.cane.len());
+    }
+
+    /// Also checks that the order of the elements is right,
+    /// which iterating can't tell us.
+    fn check_order(&self) {
+        assert_eq!(&**self.cane.write(), &self.model[..]);
+    }
+}
+
+fn sorted(items: Option<Vec<Item>>) -> Option<Vec<Item>> {
+    items.map(|mut items| {
+        items.sort_by_key(|item| item.id);
+        items
+    })
+}
+
+fn run<const SLICES: usize, S: Edits>(seed: u64, steps: usize) {
+    let mut rng = Rng::new(seed);
+    let mut harness = Harness::<SLICES, S>::new(rng.below(SLICES * 4), seed % 2 == 1);
+    let mut history = Vec::new();
+
+    for step in 0..steps {
+        let op = random_op(&mut rng, harness.model.len());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            harness.apply(&op);
+            harness.check_contents();
+            if step % 8 == 0 {
+                harness.check_order();
+            }
+        }));
+
+        history.push(op);
+
+        if let Err(e) = result {
+            eprintln!("seed {} with {} slices failed at step {}, after:", seed, SLICES, step);
+            for op in &history {
+                eprintln!("    {:?}", op);
+            }
+            std::panic::resume_unwind(e);
+        }
+    }
+
+    assert_eq!(harness.cane.into_inner(), harness.model);
+}
+
+const SEEDS: u64 = if cfg!(miri) { 2 } else { 16 };
+const STEPS: usize = if cfg!(miri) { 40 } else { 300 };
+
+#[test]
+fn model_1_slice() {
+    (0..SEEDS).for_each(|seed| run::<1, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_2_slices() {
+    (0..SEEDS).for_each(|seed| run::<2, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_3_slices() {
+    (0..SEEDS).for_each(|seed| run::<3, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_5_slices() {
+    (0..SEEDS).for_each(|seed| run::<5, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_8_slices() {
+    (0..SEEDS).for_each(|seed| run::<8, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_16_slices() {
+    (0..SEEDS).for_each(|seed| run::<16, Contiguous>(seed, STEPS));
+}
+
+#[test]
+fn model_segmented_1_slice() {
+    (0..SEEDS).for_each(|seed| run::<1, Segmented>(seed, STEPS));
+}
+
+#[test]
+fn model_segmented_3_slices() {
+    (0..SEEDS).for_each(|seed| run::<3, Segmented>(seed, STEPS));
+}
+
+#[test]
+fn model_segmented_8_slices() {
+    (0..SEEDS).for_each(|seed| run::<8, Segmented>(seed, STEPS));
+}
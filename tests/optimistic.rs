@@ -0,0 +1,193 @@
+use crate::common::panic_message;
+use candy_cane::RawCandyCane;
+use parking_lot::{RawMutex, RawRwLock};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane<T> = RawCandyCane<RawRwLock, RawMutex, T, 4>;
+
+#[test]
+fn copies() {
+    let cane = Cane::from_vec((0..10).collect::<Vec<usize>>());
+    cane.reserve(1);
+    cane.push(10);
+
+    assert_eq!(cane.get_copy(3), Some(3));
+    assert_eq!(cane.get_copy(10), Some(10));
+    assert_eq!(cane.get_copy(11), None);
+
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.read_chunk_copy(0), Some(vec![0, 1]));
+    // Along with what was appended.
+    assert_eq!(cane.read_chunk_copy(3), Some(vec![6, 7, 8, 9, 10]));
+    assert_eq!(cane.read_chunk_copy(4), None);
+}
+
+#[test]
+fn readers_of_the_chunk_dont_stop_it() {
+    let cane = Cane::from_vec((0..8).collect::<Vec<usize>>());
+
+    let mut iter = cane.iter_streaming(..);
+    let &first = iter.next().unwrap();
+    // Would wait for ourselves if it locked the chunk.
+    assert_eq!(cane.get_copy(first), Some(first));
+    assert_eq!(cane.read_chunk_copy(first / 2), Some(vec![first / 2 * 2, first / 2 * 2 + 1]));
+}
+
+#[test]
+fn waits_for_writers_of_the_chunk() {
+    let cane = Cane::from_vec(vec![0usize; 4]);
+    let started = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut range = cane.write_range(0..1);
+            started.wait();
+            std::thread::sleep(Duration::from_millis(50));
+            range[0] = 1;
+        });
+
+        started.wait();
+        assert_eq!(cane.get_copy(0), Some(1));
+        // Other chunks aren't being written.
+        assert_eq!(cane.get_copy(1), Some(0));
+    });
+}
+
+// Unlike other self-deadlocks, these panic in release builds too.
+#[test]
+fn reading_a_chunk_this_thread_writes() {
+    let cane = Cane::from_vec(vec![1usize, 2, 3, 4]);
+
+    let message = panic_message(|| {
+        let mut iter = cane.iter_streaming_mut(..1);
+        *iter.next().unwrap() += 1;
+        cane.get_copy(0);
+    });
+    assert!(message.starts_with("self-deadlock: waiting for the lock on chunk 0"), "{}", message);
+
+    let message = panic_message(|| {
+        let _range = cane.write_range(1..2);
+        cane.read_chunk_copy(1);
+    });
+    assert!(message.starts_with("self-deadlock: waiting for the lock on chunk 1"), "{}", message);
+
+    // Reading it doesn't count.
+    let mut iter = cane.iter_streaming(..1);
+    iter.next();
+    assert_eq!(cane.get_copy(0), Some(2));
+}
+
+#[test]
+fn reading_a_chunk_this_thread_was_lent() {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 1>::from_vec((0..1000).collect());
+    let owned = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut owner = cane.iter_streaming_mut(..);
+            owner.next();
+            owned.wait();
+            owned.wait();
+            while owner.next().is_some() {}
+        });
+
+        owned.wait();
+        let message = panic_message(|| {
+            // Steals from the owner, who waits for it to be
+            // given back before it lets go of the chunk.
+            let mut thief = cane.iter_streaming_mut(..);
+            thief.next();
+            cane.get_copy(0);
+        });
+        owned.wait();
+        assert!(message.contains("this thread has part of it lent"), "{}", message);
+    });
+    assert_eq!(cane.get_copy(999), Some(999));
+}
+
+/// Sets every word of `x` to one more than its first, one at a
+/// time, so that a copy taken in between would be torn.
+fn bump(x: &mut [u64; 4]) {
+    let next = x[0] + 1;
+    for word in x.iter_mut() {
+        *word = black_box(next);
+    }
+}
+
+fn is_whole(x: &[u64; 4]) -> bool {
+    x.iter().all(|&word| word == x[0])
+}
+
+/// Counts its thread out when it's done, even by panicking.
+struct Leaving<'a>(&'a AtomicUsize);
+
+impl Drop for Leaving<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+// Copies race with writes on purpose, and are thrown away when they
+// did, but Miri reports the race all the same.
+#[cfg_attr(miri, ignore)]
+fn no_torn_reads() {
+    const ROUNDS: usize = 4000;
+    const READS: usize = 20000;
+    const READERS: usize = 3;
+    let cane = Cane::from_vec(vec![[0u64; 4]; 1024]);
+    let rounds = AtomicUsize::new(0);
+    let readers = AtomicUsize::new(READERS);
+    // Until both sides have had plenty of chances to overlap.
+    let done = || rounds.load(Ordering::Relaxed) >= ROUNDS && readers.load(Ordering::Relaxed) == 0;
+
+    std::thread::scope(|s| {
+        // Mutable iterators, which steal from each other, and from
+        // the readers' iterators.
+        for _ in 0..2 {
+            s.spawn(|| {
+                while !done() {
+                    let mut iter = cane.iter_streaming_mut(..);
+                    while let Some(x) = iter.next() {
+                        bump(x);
+                    }
+                    rounds.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+
+        s.spawn(|| {
+            let mut start = 0;
+            while !done() {
+                start = (start + 97) % 1000;
+                cane.write_range(start..start + 24).iter_mut().for_each(bump);
+            }
+        });
+
+        for reader in 0..READERS {
+            let (cane, readers) = (&cane, &readers);
+            s.spawn(move || {
+                let _leaving = Leaving(readers);
+                let mut index = reader;
+                for _ in 0..READS / READERS {
+                    index = (index * 31 + 7) % 1024;
+                    assert!(is_whole(&cane.get_copy(index).unwrap()));
+                    let chunk = cane.read_chunk_copy(index % 4).unwrap();
+                    assert!(chunk.iter().all(is_whole));
+
+                    if index % 8 == 0 {
+                        let mut iter = cane.iter_streaming(..);
+                        while let Some(x) = iter.next() {
+                            assert!(is_whole(x));
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    assert!(cane.into_inner().iter().all(is_whole));
+}
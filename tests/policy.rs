@@ -0,0 +1,100 @@
+use candy_cane::{ChunkPolicy, RawCandyCane, ReverseScan, RoundRobin, Sticky, ThreadHashed};
+use parking_lot::{RawMutex, RawRwLock};
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 4>;
+
+/// Every chunk is two elements long, so an element tells
+/// us which chunk it came from.
+fn with_policy(policy: impl ChunkPolicy) -> Cane {
+    let cane = Cane::from_vec((0..8).collect());
+    cane.set_chunk_policy(policy);
+    cane
+}
+
+fn visit_order(cane: &Cane) -> Vec<usize> {
+    let mut iter = cane.iter_streaming(..);
+    let mut order = Vec::new();
+    while let Some(&x) = iter.next() {
+        order.push(x);
+    }
+    order
+}
+
+#[test]
+fn reverse_scan() {
+    let cane = with_policy(ReverseScan);
+    assert_eq!(visit_order(&cane), [6, 7, 4, 5, 2, 3, 0, 1]);
+}
+
+#[test]
+fn reverse_scan_is_the_default() {
+    let cane = Cane::from_vec((0..8).collect());
+    assert_eq!(visit_order(&cane), [6, 7, 4, 5, 2, 3, 0, 1]);
+}
+
+#[test]
+fn round_robin() {
+    let cane = with_policy(RoundRobin::default());
+    assert_eq!(visit_order(&cane), [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(visit_order(&cane), [2, 3, 4, 5, 6, 7, 0, 1]);
+    assert_eq!(visit_order(&cane), [4, 5, 6, 7, 0, 1, 2, 3]);
+
+    // Only over the chunks the range covers.
+    let mut iter = cane.iter_streaming(1..4);
+    assert_eq!(iter.next(), Some(&2));
+}
+
+#[test]
+fn thread_hashed() {
+    let cane = with_policy(ThreadHashed);
+    let first = visit_order(&cane);
+    assert_eq!(visit_order(&cane), first);
+    assert_eq!(first.iter().sum::<usize>(), 28);
+}
+
+#[test]
+fn sticky() {
+    let cane = with_policy(Sticky::default());
+    assert_eq!(visit_order(&cane), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+    // Picks up from the chunk it took last.
+    assert_eq!(visit_order(&cane), [6, 7, 0, 1, 2, 3, 4, 5]);
+
+    let mut iter = cane.iter_streaming(2..4);
+    iter.next();
+    drop(iter);
+    assert_eq!(visit_order(&cane), [2, 3, 4, 5, 6, 7, 0, 1]);
+
+    // Which is remembered per cane.
+    let other = with_policy(Sticky::default());
+    assert_eq!(visit_order(&other), [0, 1, 2, 3, 4, 5, 6, 7]);
+}
+
+fn visits_everything(policy: impl ChunkPolicy) {
+    const THREADS: usize = 4;
+    const LEN: usize = if cfg!(miri) { 40 } else { 1000 };
+
+    let cane = Cane::from_vec(vec![0; LEN]);
+    cane.set_chunk_policy(policy);
+
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                let mut iter = cane.iter_streaming_mut(..);
+                while let Some(x) = iter.next() {
+                    *x += 1;
+                }
+            });
+        }
+    });
+
+    assert!(cane.into_inner().iter().all(|&x| x == THREADS));
+}
+
+#[test]
+fn every_policy_visits_everything() {
+    visits_everything(ReverseScan);
+    visits_everything(RoundRobin::default());
+    visits_everything(ThreadHashed);
+    visits_everything(Sticky::default());
+}
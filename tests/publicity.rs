@@ -1,19 +1,104 @@
 use candy_cane::RawCandyCane;
 use candy_cane::CandyCane;
 use candy_cane::CandyCaneWriteGuard;
+use candy_cane::CandyCaneRangeGuard;
+use candy_cane::CandyCaneTransaction;
+use candy_cane::CandyCaneUpgradableRead;
+use candy_cane::{RcuCandyCane, RcuReadGuard, RcuWriteGuard};
+use candy_cane::Adaptive;
+use candy_cane::WaitStrategy;
+use candy_cane::{IterState, OnLayoutChange};
+use candy_cane::{ChunkBounds, Contiguous, Segmented, Storage};
+use candy_cane::{ChunkPolicy, ReverseScan, RoundRobin, Scan, Sticky, ThreadHashed};
 
 use candy_cane::iter::streaming::RawCandyCaneIterStreaming;
 use candy_cane::iter::streaming::CandyCaneIterStreamingMut;
 use candy_cane::iter::streaming::CandyCaneIterStreaming;
+use candy_cane::iter::RangeError;
 
-use parking_lot::RawRwLock;
+use parking_lot::{RawRwLock, RawMutex};
 
 #[test]
 fn everything_is_accessible() {
-    let cane: RawCandyCane<_, _, 6> = CandyCane::<()>::new();
-    let _: CandyCaneWriteGuard<_, _, 6> = cane.write();
+    let cane: RawCandyCane<_, _, _, 6> = CandyCane::<()>::new();
+    let _: CandyCaneWriteGuard<_, _, _, 6> = cane.write();
+    let _: CandyCaneRangeGuard<_, _, _> = cane.write_range(..);
+    let _: Result<CandyCaneRangeGuard<_, _, _>, RangeError> = cane.try_write_range(..);
+    let transaction: CandyCaneTransaction<_, _, _, 6> = cane.write_transaction();
+    transaction.abort();
+    cane.write_transaction().commit();
+    {
+        let read: CandyCaneUpgradableRead<_, _, _, 6> = cane.upgradable_read();
+        let _ = (read.len(), read.is_empty());
+        let _: CandyCaneIterStreaming<_, _> = read.iter_streaming(..);
+        let _: Result<CandyCaneIterStreaming<_, _>, RangeError> = read.try_iter_streaming(..);
+        let mut state = IterState::new();
+        let _: CandyCaneIterStreaming<_, _> = read.iter_streaming_with(&mut state, ..);
+        let _: CandyCaneWriteGuard<_, _, _, 6> = read.upgrade();
+    }
+    cane.reserve(2);
+    cane.push(());
+    cane.extend([()]);
 
-    let _: RawCandyCaneIterStreaming<'_, RawRwLock, ()>;
+    let _: RawCandyCaneIterStreaming<'_, RawRwLock, RawMutex, ()>;
     let _: CandyCaneIterStreaming<_, _> = cane.iter_streaming(..);
     let _: CandyCaneIterStreamingMut<_, _> = cane.iter_streaming_mut(..);
+    let _: Result<CandyCaneIterStreaming<_, _>, RangeError> = cane.try_iter_streaming(..);
+    let _: Result<CandyCaneIterStreamingMut<_, _>, RangeError> = cane.try_iter_streaming_mut(..);
+
+    let iter: CandyCaneIterStreaming<_, _> = cane.iter_streaming_interruptible(.., OnLayoutChange::Replan);
+    let _: bool = iter.layout_changed();
+    drop(iter);
+    let iter: CandyCaneIterStreamingMut<_, _> = cane.iter_streaming_mut_interruptible(.., OnLayoutChange::Stop);
+    let _: bool = iter.layout_changed();
+    drop(iter);
+    let _: Result<CandyCaneIterStreaming<_, _>, RangeError> = cane.try_iter_streaming_interruptible(.., OnLayoutChange::Stop);
+    let _: Result<CandyCaneIterStreamingMut<_, _>, RangeError> = cane.try_iter_streaming_mut_interruptible(.., OnLayoutChange::Stop);
+    let _ = RawCandyCaneIterStreaming::new_interruptible(.., &cane, OnLayoutChange::Stop);
+
+    let mut state = IterState::new();
+    let _: CandyCaneIterStreaming<_, _> = cane.iter_streaming_with(&mut state, ..);
+    let _: CandyCaneIterStreamingMut<_, _> = cane.iter_streaming_mut_with(&mut state, ..);
+    let _: Result<CandyCaneIterStreaming<_, _>, RangeError> = cane.try_iter_streaming_with(&mut state, ..);
+    let _: Result<CandyCaneIterStreamingMut<_, _>, RangeError> = cane.try_iter_streaming_mut_with(&mut state, ..);
+
+    cane.set_adaptive(Some(Adaptive::default()));
+    cane.rebalance();
+    let _: usize = cane.chunk_count();
+
+    let _: Scan = RoundRobin::default().scan(0..1);
+    cane.set_chunk_policy(ReverseScan);
+    cane.set_chunk_policy(ThreadHashed);
+    cane.set_chunk_policy(Sticky::default());
+    cane.set_wait_strategy(WaitStrategy::Park);
+
+    let _: Option<()> = cane.get_copy(0);
+    let _: Option<Vec<()>> = cane.read_chunk_copy(0);
+
+    fn storage<S: Storage>(_: RawCandyCane<RawRwLock, RawMutex, (), 6, S>) {}
+    storage::<Contiguous>(cane);
+    let segmented = RawCandyCane::<RawRwLock, RawMutex, (), 6, Segmented>::new();
+    segmented.set_chunk_bounds(ChunkBounds::default());
+    segmented.insert(0, ());
+    segmented.swap_remove(0);
+    segmented.push(());
+    segmented.remove(0);
+    storage(segmented);
+
+    let rcu: RcuCandyCane<()> = RcuCandyCane::default();
+    {
+        let read: RcuReadGuard<_> = rcu.read();
+        let _ = (read.len(), read.is_empty(), read.get(0), read.iter(), read.chunks());
+        let mut write: RcuWriteGuard<_> = rcu.write();
+        let _ = (write.len(), write.is_empty(), write.get(0), write.iter());
+        write.push(());
+        let _ = write.get_mut(0);
+        let _ = write.iter_mut();
+        let _ = write.pop();
+        write.truncate(0);
+        write.clear();
+    }
+    let _ = (rcu.len(), rcu.is_empty());
+    let _: Vec<()> = rcu.into_inner();
+    let _: RcuCandyCane<(), 2> = RcuCandyCane::new();
 }
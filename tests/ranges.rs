@@ -0,0 +1,121 @@
+use candy_cane::RawCandyCane;
+use parking_lot::{RawRwLock, RawMutex};
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
+use std::slice::SliceIndex;
+
+/// Every shape of range, with bounds a little past `len`.
+fn all_ranges(len: usize) -> Vec<(Bound<usize>, Bound<usize>)> {
+    let mut bounds = vec![Bound::Unbounded];
+    for i in 0..len + 3 {
+        bounds.push(Bound::Included(i));
+        bounds.push(Bound::Excluded(i));
+    }
+
+    let mut ranges = Vec::new();
+    for &start in &bounds {
+        for &end in &bounds {
+            ranges.push((start, end));
+        }
+    }
+
+    ranges
+}
+
+fn check_range<R, const SLICES: usize>(cane: &RawCandyCane<RawRwLock, RawMutex, usize, SLICES>, model: &[usize], range: R)
+where
+    R: RangeBounds<usize> + SliceIndex<[usize], Output = [usize]> + Clone + Debug,
+{
+    let expected = model.get(range.clone());
+
+    let found = cane.try_iter_streaming(range.clone()).ok().map(|mut iter| {
+        let mut found = Vec::new();
+        while let Some(&x) = iter.next() {
+            found.push(x);
+        }
+        found.sort_unstable();
+        found
+    });
+    assert_eq!(expected.map(<[usize]>::to_vec), found, "{:?} over {} with {} slices", range, model.len(), SLICES);
+
+    let found_mut = cane.try_iter_streaming_mut(range.clone()).ok().map(|mut iter| {
+        let mut found = Vec::new();
+        while let Some(x) = iter.next() {
+            found.push(*x);
+        }
+        found.sort_unstable();
+        found
+    });
+    assert_eq!(expected.map(<[usize]>::to_vec), found_mut, "{:?} over {} with {} slices (mut)", range, model.len(), SLICES);
+
+    // Single elements and whole batches, taken in turns.
+    let found_slices = cane.try_iter_streaming(range.clone()).ok().map(|mut iter| {
+        let mut found = Vec::new();
+        while let Some(&x) = iter.next() {
+            found.push(x);
+            match iter.next_slice() {
+                Some(slice) => found.extend_from_slice(slice),
+                None => break,
+            }
+        }
+        found.sort_unstable();
+        found
+    });
+    assert_eq!(expected.map(<[usize]>::to_vec), found_slices, "{:?} over {} with {} slices (slices)", range, model.len(), SLICES);
+}
+
+fn check_all<const SLICES: usize>() {
+    // Under Miri, only lengths that leave the chunks empty, one
+    // element each, and with leftovers in the last one.
+    let lens = if cfg!(miri) { vec![0, 1, SLICES, SLICES + 1] } else { (0..SLICES * 3 + 2).collect() };
+    for len in lens {
+        let model = (0..len).collect::<Vec<_>>();
+        let cane = RawCandyCane::<RawRwLock, RawMutex, _, SLICES>::from_vec(model.clone());
+
+        for range in all_ranges(len) {
+            check_range(&cane, &model, range);
+        }
+
+        check_range(&cane, &model, ..);
+        check_range(&cane, &model, ..len);
+        check_range(&cane, &model, len / 2..);
+    }
+}
+
+#[test]
+fn ranges_match_vec_1() {
+    check_all::<1>();
+}
+
+#[test]
+fn ranges_match_vec_3() {
+    check_all::<3>();
+}
+
+#[test]
+fn ranges_match_vec_4() {
+    check_all::<4>();
+}
+
+#[test]
+fn ranges_match_vec_7() {
+    check_all::<7>();
+}
+
+#[test]
+fn empty_cane() {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 4>::new();
+
+    assert!(cane.iter_streaming(..).next().is_none());
+    assert!(cane.iter_streaming_mut(0..0).next().is_none());
+    assert!(cane.try_iter_streaming(..1).is_err());
+    assert!(cane.try_iter_streaming_mut(1..).is_err());
+}
+
+#[test]
+#[should_panic]
+fn out_of_bounds_panics() {
+    let _x = hushed_panic::hush_this_test();
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 4>::from_vec(vec![0; 10]);
+    cane.iter_streaming(..11);
+}
@@ -0,0 +1,174 @@
+use candy_cane::RcuCandyCane;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+
+type Cane<T> = RcuCandyCane<T, 4>;
+
+/// Where each chunk's elements are.
+fn chunk_addresses<T>(cane: &Cane<T>) -> Vec<*const T> {
+    cane.read().chunks().map(|chunk| chunk.as_ptr()).collect()
+}
+
+#[test]
+fn readers_keep_their_version() {
+    let cane = Cane::from_vec((0..300).collect::<Vec<usize>>());
+
+    let before = cane.read();
+    let mut guard = cane.write();
+    *guard.get_mut(0).unwrap() = 1000;
+    guard.push(300);
+    // Not published until it's dropped.
+    assert_eq!(cane.read().get(0), Some(&0));
+    drop(guard);
+
+    assert_eq!(before.len(), 300);
+    assert!(before.iter().copied().eq(0..300));
+    let after = cane.read();
+    assert_eq!(after.len(), 301);
+    assert!(after.iter().copied().eq(std::iter::once(1000).chain(1..301)));
+}
+
+#[test]
+fn only_changed_chunks_are_cloned() {
+    // Four chunks of 64.
+    let cane = Cane::from_vec((0..256).collect::<Vec<usize>>());
+    let before = chunk_addresses(&cane);
+    assert_eq!(before.len(), 4);
+
+    *cane.write().get_mut(70).unwrap() += 1;
+    let after = chunk_addresses(&cane);
+    assert_eq!([after[0], after[2], after[3]], [before[0], before[2], before[3]]);
+    assert_ne!(after[1], before[1]);
+
+    // The last chunk is full, so this starts another.
+    cane.write().push(256);
+    assert_eq!(chunk_addresses(&cane)[..4], after[..]);
+
+    // And nothing is cloned if nothing is changed.
+    let guard = cane.write();
+    assert_eq!(guard.get(70), Some(&71));
+    drop(guard);
+    assert_eq!(chunk_addresses(&cane)[..4], after[..]);
+}
+
+#[test]
+fn like_a_vec() {
+    let cane = Cane::from_vec((0..200).collect::<Vec<usize>>());
+    let mut model = (0..200).collect::<Vec<usize>>();
+
+    let mut guard = cane.write();
+    for x in 200..300 {
+        guard.push(x);
+    }
+    assert_eq!(guard.pop(), Some(299));
+    guard.truncate(130);
+    guard.iter_mut().for_each(|x| *x *= 2);
+    guard.truncate(128);
+    guard.push(7);
+    drop(guard);
+
+    model.extend(200..300);
+    assert_eq!(model.pop(), Some(299));
+    model.truncate(130);
+    model.iter_mut().for_each(|x| *x *= 2);
+    model.truncate(128);
+    model.push(7);
+
+    assert_eq!(cane.len(), model.len());
+    assert!(cane.read().iter().eq(&model));
+    assert_eq!(cane.read().get(128), Some(&7));
+    assert_eq!(cane.read().get(129), None);
+
+    let mut guard = cane.write();
+    guard.clear();
+    assert_eq!(guard.pop(), None);
+    guard.push(1);
+    drop(guard);
+    assert_eq!(cane.into_inner(), [1]);
+}
+
+#[test]
+fn old_versions_are_freed_once_unread() {
+    let cane = Cane::from_vec(vec![Arc::new(0)]);
+    let first = Arc::downgrade(cane.read().get(0).unwrap());
+
+    let reader = cane.read();
+    *cane.write().get_mut(0).unwrap() = Arc::new(1);
+    // Still in the version `reader` is looking at.
+    assert_eq!(first.strong_count(), 1);
+
+    // Readers that started later don't keep it.
+    let later = cane.read();
+    drop(reader);
+    drop(cane.write());
+    assert_eq!(first.strong_count(), 0);
+    assert_eq!(**later.get(0).unwrap(), 1);
+}
+
+#[test]
+fn readers_dont_wait_for_writers() {
+    let cane = Cane::from_vec(vec![0usize; 100]);
+    let read = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        let mut guard = cane.write();
+        guard.iter_mut().for_each(|x| *x = 1);
+
+        s.spawn(|| {
+            assert!(cane.read().iter().all(|&x| x == 0));
+            read.wait();
+        });
+
+        // The reader got through while the guard was held.
+        read.wait();
+        drop(guard);
+    });
+    assert!(cane.read().iter().all(|&x| x == 1));
+}
+
+#[test]
+fn readers_and_writers() {
+    const WRITES: usize = if cfg!(miri) { 20 } else { 2000 };
+    let cane = Cane::from_vec(vec![0usize; 200]);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..3 {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    // Every write sets every element to the same
+                    // value, so a version mixing two writes shows.
+                    let read = cane.read();
+                    let first = *read.get(0).unwrap();
+                    assert!(read.iter().all(|&x| x == first));
+                    assert_eq!(read.len(), 200 + first % 2);
+                }
+            });
+        }
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                for _ in 0..WRITES / 2 {
+                    let mut guard = cane.write();
+                    let next = guard.get(0).unwrap() + 1;
+                    if next % 2 == 1 {
+                        guard.push(0);
+                    } else {
+                        guard.pop();
+                    }
+                    guard.iter_mut().for_each(|x| *x = next);
+                }
+            });
+        }
+
+        s.spawn(|| {
+            // Lets the readers go once both writers are done.
+            while cane.read().get(0) != Some(&WRITES) {
+                std::thread::yield_now();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+    });
+
+    assert_eq!(cane.into_inner(), vec![WRITES; 200]);
+}
@@ -0,0 +1,102 @@
+use candy_cane::{IterState, RawCandyCane};
+use parking_lot::{RawMutex, RawRwLock};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts allocations per thread, so that tests running
+/// alongside don't show up in each other's counts.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: Everything is passed on to `System`.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|x| x.set(x.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 4>;
+
+fn sum(cane: &Cane, state: &mut IterState, range: std::ops::Range<usize>) -> usize {
+    let mut iter = cane.iter_streaming_with(state, range);
+    let mut sum = 0;
+    while let Some(x) = iter.next() {
+        sum += x;
+    }
+    sum
+}
+
+#[test]
+fn reused_state_doesnt_allocate() {
+    let cane = Cane::from_vec((0..1000).collect());
+    let mut state = IterState::new();
+    // Sizes the state, and in debug builds the
+    // registry of locks held by this thread.
+    assert_eq!(sum(&cane, &mut state, 0..1000), 499500);
+
+    let before = allocations();
+    for _ in 0..10 {
+        assert_eq!(sum(&cane, &mut state, 0..1000), 499500);
+        assert_eq!(sum(&cane, &mut state, 250..750), (250..750).sum());
+    }
+    assert_eq!(allocations(), before);
+}
+
+#[test]
+fn state_moves_between_canes() {
+    let small = Cane::from_vec((0..10).collect());
+    let large = RawCandyCane::<RawRwLock, RawMutex, usize, 200>::from_vec((0..1000).collect());
+    let mut state = IterState::new();
+
+    assert_eq!(sum(&small, &mut state, 0..10), 45);
+    let mut iter = large.iter_streaming_mut_with(&mut state, ..);
+    while let Some(x) = iter.next() {
+        *x += 1;
+    }
+    drop(iter);
+    assert_eq!(sum(&small, &mut state, 5..10), 35);
+
+    let mut iter = large.iter_streaming_with(&mut state, ..);
+    let mut total = 0;
+    while let Some(&x) = iter.next() {
+        total += x;
+    }
+    assert_eq!(total, (1..1001).sum());
+}
+
+fn sum_fresh(cane: &Cane, range: std::ops::Range<usize>) -> usize {
+    let mut iter = cane.iter_streaming(range);
+    let mut sum = 0;
+    while let Some(x) = iter.next() {
+        sum += x;
+    }
+    sum
+}
+
+#[test]
+fn fresh_state_doesnt_allocate() {
+    let cane = Cane::from_vec((0..1000).collect());
+    // Sizes the registry of locks held by
+    // this thread in debug builds.
+    assert_eq!(sum_fresh(&cane, 0..1000), 499500);
+
+    let before = allocations();
+    assert_eq!(sum_fresh(&cane, 0..1000), 499500);
+    assert_eq!(sum_fresh(&cane, 250..750), (250..750).sum());
+    assert_eq!(allocations(), before);
+}
@@ -0,0 +1,88 @@
+use candy_cane::{ChunkStats, RawCandyCane};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 2>;
+
+#[test]
+fn uncontended() {
+    let cane = Cane::from_vec(vec![1, 2, 3, 4]);
+
+    let mut iter = cane.iter_streaming(..);
+    while iter.next().is_some() {}
+    drop(iter);
+    cane.write().push(5);
+
+    let stats = cane.stats();
+    for chunk in &stats.chunks {
+        assert_eq!(chunk.try_lock_successes, 1);
+        assert_eq!(chunk.try_lock_failures, 0);
+        assert_eq!(chunk.blocking_locks, 0);
+    }
+    assert_eq!(stats.chunks.len(), 2);
+    assert_eq!(stats.readers_gated, 0);
+    assert_eq!(stats.writes, 1);
+
+    cane.reset_stats();
+    let stats = cane.stats();
+    assert_eq!(stats.chunks, vec![ChunkStats::default(); 2]);
+    assert_eq!(stats.writes, 0);
+    assert_eq!(stats.write_hold_time, Duration::ZERO);
+}
+
+#[test]
+fn contended_chunk() {
+    let cane = Cane::from_vec(vec![1, 2, 3, 4]);
+
+    std::thread::scope(|s| {
+        let mut held = cane.iter_streaming_mut(..2);
+        held.next();
+
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming_mut(..);
+            while iter.next().is_some() {}
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+    });
+
+    let stats = cane.stats();
+    // The board showed it as taken, so it was never tried.
+    assert_eq!(stats.chunks[0].try_lock_failures, 0);
+    assert_eq!(stats.chunks[0].blocking_locks, 1);
+    assert!(stats.chunks[0].hold_time >= Duration::from_millis(50));
+    assert_eq!(stats.chunks[1].try_lock_successes, 1);
+}
+
+#[test]
+fn gated_reader() {
+    let cane = Cane::from_vec(vec![1, 2, 3, 4]);
+    let started = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        let iter = cane.iter_streaming(..);
+
+        s.spawn(|| {
+            started.wait();
+            cane.write().push(5)
+        });
+        started.wait();
+        std::thread::sleep(Duration::from_millis(50));
+
+        // The writer is waiting on `iter`, so the gate is closed.
+        s.spawn(|| {
+            started.wait();
+            cane.len()
+        });
+        started.wait();
+        std::thread::sleep(Duration::from_millis(50));
+
+        drop(iter);
+    });
+
+    let stats = cane.stats();
+    assert_eq!(stats.readers_gated, 1);
+    assert_eq!(stats.writes, 1);
+    assert!(stats.write_wait_time >= Duration::from_millis(50));
+}
@@ -0,0 +1,131 @@
+use candy_cane::{RawCandyCane, WaitStrategy};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+#[derive(Default)]
+struct Item {
+    visits: usize,
+    /// Set while an iterator has a `&mut` to this item.
+    busy: AtomicBool,
+}
+
+impl Item {
+    fn visit(&mut self) {
+        assert!(!self.busy.swap(true, Ordering::SeqCst), "two iterators hold the same element");
+        self.visits += 1;
+        self.busy.store(false, Ordering::SeqCst);
+    }
+}
+
+type Cane<const SLICES: usize> = RawCandyCane<RawRwLock, RawMutex, Item, SLICES>;
+
+fn items(len: usize) -> Vec<Item> {
+    (0..len).map(|_| Item::default()).collect()
+}
+
+#[test]
+fn lends_to_idle_iterators() {
+    let cane = Cane::<1>::from_vec(items(64));
+    let seen = AtomicUsize::new(0);
+    let started = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        let mut held = cane.iter_streaming_mut(..);
+        // Claims the first batch, leaving the rest published.
+        held.next().unwrap().visit();
+
+        s.spawn(|| {
+            started.wait();
+            let mut iter = cane.iter_streaming_mut(..);
+            while let Some(x) = iter.next() {
+                x.visit();
+                seen.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        started.wait();
+        // The thief keeps taking the back half of what's left,
+        // 16 + 8 + 4 + 2 + 1 elements, until only one element is
+        // left, and then waits for the chunk's lock.
+        while seen.load(Ordering::SeqCst) < 31 {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(seen.load(Ordering::SeqCst), 31);
+
+        while let Some(x) = held.next() {
+            x.visit();
+        }
+    });
+
+    assert_eq!(seen.into_inner(), 64);
+    assert!(cane.into_inner().iter().all(|x| x.visits == 2));
+}
+
+#[test]
+fn owner_stopping_early_waits_for_thieves() {
+    let cane = Cane::<1>::from_vec(items(64));
+    let started = Barrier::new(2);
+
+    std::thread::scope(|s| {
+        let mut held = cane.iter_streaming_mut(..);
+        held.next().unwrap().visit();
+
+        s.spawn(|| {
+            started.wait();
+            let mut iter = cane.iter_streaming_mut(..);
+            while let Some(x) = iter.next() {
+                x.visit();
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        started.wait();
+        std::thread::sleep(Duration::from_millis(5));
+        drop(held);
+    });
+
+    let items = cane.into_inner();
+    assert_eq!(items.iter().map(|x| x.visits).sum::<usize>(), 65);
+    assert_eq!(items[0].visits, 2);
+}
+
+#[test]
+fn uneven_cost() {
+    const THREADS: usize = 4;
+    const LEN: usize = if cfg!(miri) { 64 } else { 512 };
+
+    // Owners wait for thieves the way the strategy says.
+    for wait in [WaitStrategy::Block, WaitStrategy::Spin, WaitStrategy::Park] {
+        let cane = Cane::<2>::from_vec(items(LEN));
+        cane.set_wait_strategy(wait);
+        let started = Barrier::new(THREADS);
+
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    started.wait();
+                    let mut iter = cane.iter_streaming_mut(..);
+                    let mut count = 0;
+                    while let Some(x) = iter.next() {
+                        x.visit();
+                        count += 1;
+                        // Every so often an element is slow, so that the
+                        // iterators fall out of step with each other.
+                        if count % 8 == 0 {
+                            std::thread::sleep(Duration::from_micros(200));
+                        }
+                    }
+                    assert_eq!(count, LEN);
+                });
+            }
+        });
+
+        #[cfg(feature = "stats")]
+        assert!(cane.stats().chunks.iter().map(|x| x.steals).sum::<u64>() > 0, "{:?}", wait);
+
+        assert!(cane.into_inner().iter().all(|x| x.visits == THREADS), "{:?}", wait);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::common::visited;
+use candy_cane::{RawCandyCane, Segmented};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::Arc;
+
+type Cane<T> = RawCandyCane<RawRwLock, RawMutex, T, 4, Segmented>;
+
+fn addresses(cane: &Cane<usize>, range: std::ops::Range<usize>) -> Vec<(usize, *const usize)> {
+    let mut iter = cane.iter_streaming(range);
+    let mut addresses = Vec::new();
+    while let Some(x) = iter.next() {
+        addresses.push((*x, x as *const usize));
+    }
+    addresses.sort_unstable();
+    addresses
+}
+
+#[test]
+fn iterates_like_contiguous() {
+    let cane = Cane::from_vec((0..10).collect());
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(visited(&cane), (0..10).collect::<Vec<_>>());
+
+    let mut iter = cane.iter_streaming_mut(3..8);
+    while let Some(x) = iter.next() {
+        *x *= 10;
+    }
+    drop(iter);
+    assert_eq!(cane.into_inner(), [0, 1, 2, 30, 40, 50, 60, 70, 8, 9]);
+}
+
+#[test]
+fn appending_doesnt_move_elements() {
+    let cane = Cane::from_vec((0..8).collect());
+    let before = addresses(&cane, 0..8);
+
+    cane.extend(8..300);
+    cane.push(300);
+    assert_eq!(cane.len(), 301);
+    assert!(cane.chunk_count() > 4);
+    assert_eq!(addresses(&cane, 0..8), before);
+    assert_eq!(visited(&cane), (0..301).collect::<Vec<_>>());
+
+    // Until `write()` puts everything back into four chunks.
+    cane.write().push(301);
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(visited(&cane), (0..302).collect::<Vec<_>>());
+}
+
+#[test]
+fn appending_to_an_empty_cane() {
+    let cane = Cane::new();
+    cane.push(0);
+    cane.reserve(100);
+    let before = addresses(&cane, 0..1);
+    cane.extend(1..101);
+    assert_eq!(addresses(&cane, 0..1), before);
+
+    cane.rebalance();
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.into_inner(), (0..101).collect::<Vec<_>>());
+}
+
+#[test]
+fn appended_elements_are_dropped() {
+    let counted = Arc::new(());
+    let cane = Cane::from_vec(vec![counted.clone(); 4]);
+    (0..100).for_each(|_| cane.push(counted.clone()));
+    assert_eq!(Arc::strong_count(&counted), 105);
+    drop(cane);
+    assert_eq!(Arc::strong_count(&counted), 1);
+}
+
+#[test]
+fn producers_and_iterators() {
+    const PER_PRODUCER: usize = if cfg!(miri) { 20 } else { 2000 };
+    let cane = Cane::new();
+
+    std::thread::scope(|s| {
+        for producer in 0..4 {
+            let cane = &cane;
+            s.spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    cane.push(producer * PER_PRODUCER + i);
+                }
+            });
+        }
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                let mut last = 0;
+                while last < 4 * PER_PRODUCER {
+                    let visited = visited(&cane);
+                    // Every element at most once, and never fewer than before.
+                    visited.windows(2).for_each(|x| assert!(x[0] < x[1]));
+                    assert!(visited.len() >= last);
+                    last = visited.len();
+                }
+            });
+        }
+    });
+
+    assert_eq!(visited(&cane), (0..4 * PER_PRODUCER).collect::<Vec<_>>());
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn appending_keeps_chunk_stats() {
+    let cane = Cane::from_vec((0..8).collect());
+    visited(&cane);
+    cane.extend(8..200);
+
+    let stats = cane.stats();
+    assert!(stats.chunks.len() > 4);
+    assert!(stats.chunks[..3].iter().all(|chunk| chunk.try_lock_successes == 1));
+}
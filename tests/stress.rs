@@ -0,0 +1,202 @@
+//! Races `write()` against streaming iterators on the
+//! instrumented locks from `candy_cane::testing`.
+//!
+//! Set `CANDY_CANE_SEED` to replay a single seed.
+
+use candy_cane::testing::{Chaos, ChaosConfig, LockKind, Recorder, Rng, TestMutex, TestRwLock};
+use candy_cane::RawCandyCane;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Item {
+    id: usize,
+    visits: usize,
+    /// Set while an iterator has a `&mut` to this item.
+    busy: AtomicBool,
+}
+
+impl Item {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            visits: 0,
+            busy: AtomicBool::new(false),
+        }
+    }
+}
+
+type Cane<const SLICES: usize> = RawCandyCane<TestRwLock, TestMutex, Item, SLICES>;
+
+struct Shared<const SLICES: usize> {
+    cane: Cane<SLICES>,
+    /// Only changed under the write guard, so it's stable
+    /// for as long as an iterator is alive.
+    len: AtomicUsize,
+    next_id: AtomicUsize,
+}
+
+const READERS: usize = 3;
+const WRITERS: usize = 2;
+const ROUNDS: usize = if cfg!(miri) { 4 } else { 60 };
+
+fn check_unique(mut ids: Vec<usize>, expected: usize) {
+    assert_eq!(ids.len(), expected);
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), expected, "an element was visited twice");
+}
+
+fn reader<const SLICES: usize>(shared: &Shared<SLICES>, rng: &mut Rng) {
+    for _ in 0..ROUNDS {
+        if rng.one_in(3) {
+            let mut iter = shared.cane.iter_streaming(..);
+            let len = shared.len.load(Ordering::SeqCst);
+
+            let mut ids = Vec::new();
+            while let Some(item) = iter.next() {
+                assert!(!item.busy.load(Ordering::SeqCst), "read while mutably borrowed");
+                ids.push(item.id);
+            }
+
+            check_unique(ids, len);
+        } else {
+            let mut iter = shared.cane.iter_streaming_mut(..);
+            let len = shared.len.load(Ordering::SeqCst);
+
+            let mut ids = Vec::new();
+            while let Some(item) = iter.next() {
+                assert!(!item.busy.swap(true, Ordering::SeqCst), "two iterators hold the same element");
+                candy_cane::testing::pause();
+                item.visits += 1;
+                item.busy.store(false, Ordering::SeqCst);
+                ids.push(item.id);
+            }
+
+            check_unique(ids, len);
+        }
+    }
+}
+
+fn writer<const SLICES: usize>(shared: &Shared<SLICES>, rng: &mut Rng) {
+    for _ in 0..ROUNDS {
+        let mut guard = shared.cane.write();
+        assert!(guard.iter().all(|x| !x.busy.load(Ordering::SeqCst)));
+
+        let len = guard.len();
+        match rng.below(4) {
+            0 if len > 0 => guard.truncate(rng.below(len)),
+            1 => guard.insert(rng.up_to(len), Item::new(shared.next_id.fetch_add(1, Ordering::SeqCst))),
+            _ => {
+                let count = rng.below(16);
+                guard.extend((0..count).map(|_| Item::new(shared.next_id.fetch_add(1, Ordering::SeqCst))));
+            }
+        }
+
+        shared.len.store(guard.len(), Ordering::SeqCst);
+        drop(guard);
+
+        candy_cane::testing::pause();
+    }
+}
+
+fn stress<const SLICES: usize>(seed: u64) {
+    let recorder = Recorder::new();
+    let config = ChaosConfig {
+        yield_one_in: 3,
+        sleep_one_in: 24,
+        max_sleep: Duration::from_micros(20),
+        recorder: Some(recorder.clone()),
+    };
+
+    let initial = Rng::new(seed).below(SLICES * 8);
+    let shared = Arc::new(Shared::<SLICES> {
+        cane: RawCandyCane::from_vec((0..initial).map(Item::new).collect()),
+        len: AtomicUsize::new(initial),
+        next_id: AtomicUsize::new(initial),
+    });
+
+    let threads = (0..READERS + WRITERS)
+        .map(|index| {
+            let shared = Arc::clone(&shared);
+            let config = config.clone();
+            std::thread::spawn(move || {
+                let _chaos = Chaos::enable(seed, index, config);
+                let mut rng = Rng::new(seed.wrapping_add(index as u64));
+                if index < READERS {
+                    reader(&shared, &mut rng);
+                } else {
+                    writer(&shared, &mut rng);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = threads.into_iter().map(|x| x.join()).collect::<Vec<_>>();
+    for result in results {
+        if let Err(e) = result {
+            eprintln!("stress test with {} slices failed on seed {}", SLICES, seed);
+            std::panic::resume_unwind(e);
+        }
+    }
+
+    assert_eq!(recorder.count(LockKind::Exclusive), WRITERS * ROUNDS);
+    assert_eq!(recorder.count(LockKind::Shared), READERS * ROUNDS);
+
+    let shared = Arc::try_unwrap(shared).ok().unwrap();
+    let items = shared.cane.into_inner();
+    assert_eq!(items.len(), shared.len.into_inner());
+    assert!(items.iter().all(|x| x.visits <= READERS * ROUNDS));
+}
+
+fn seeds() -> Vec<u64> {
+    match std::env::var("CANDY_CANE_SEED") {
+        Ok(seed) => vec![seed.parse().expect("CANDY_CANE_SEED should be a u64")],
+        Err(_) if cfg!(miri) => vec![0],
+        Err(_) => (0..6).collect(),
+    }
+}
+
+#[test]
+fn stress_1_slice() {
+    seeds().into_iter().for_each(stress::<1>);
+}
+
+#[test]
+fn stress_4_slices() {
+    seeds().into_iter().for_each(stress::<4>);
+}
+
+#[test]
+fn stress_16_slices() {
+    seeds().into_iter().for_each(stress::<16>);
+}
+
+#[test]
+fn records_acquisitions_in_order() {
+    let recorder = Recorder::new();
+    let config = ChaosConfig {
+        yield_one_in: 0,
+        sleep_one_in: 0,
+        recorder: Some(recorder.clone()),
+        ..ChaosConfig::default()
+    };
+    let _chaos = Chaos::enable(0, 7, config);
+
+    let cane = RawCandyCane::<TestRwLock, TestMutex, usize, 2>::from_vec(vec![1, 2, 3, 4]);
+    cane.write().push(5);
+    let mut iter = cane.iter_streaming(..);
+    while iter.next().is_some() {}
+    drop(iter);
+
+    let kinds = recorder.acquisitions().iter().map(|x| (x.thread, x.kind)).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            (7, LockKind::Exclusive),
+            (7, LockKind::Shared),
+            (7, LockKind::Mutex),
+            (7, LockKind::Mutex),
+        ],
+    );
+}
@@ -0,0 +1,155 @@
+use candy_cane::{Contiguous, RawCandyCane, Segmented, Storage};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+type Cane<S> = RawCandyCane<RawRwLock, RawMutex, usize, 4, S>;
+
+/// Every element, with where it is, in order.
+fn addresses<S: Storage>(cane: &Cane<S>) -> Vec<(usize, *const usize)> {
+    let mut iter = cane.iter_streaming(..);
+    let mut addresses = Vec::new();
+    while let Some(x) = iter.next() {
+        addresses.push((*x, x as *const usize));
+    }
+    addresses.sort_unstable_by_key(|x| x.1);
+    addresses
+}
+
+fn scribble(values: &mut Vec<usize>) {
+    values[3] = 100;
+    values.remove(0);
+    values.extend(200..300);
+    values.truncate(50);
+}
+
+#[test]
+fn aborting_leaves_everything_as_it_was() {
+    let cane = Cane::<Contiguous>::from_vec((0..10).collect());
+    let before = addresses(&cane);
+
+    let mut transaction = cane.write_transaction();
+    scribble(&mut transaction);
+    transaction.abort();
+    assert_eq!(addresses(&cane), before);
+
+    // As does dropping it.
+    scribble(&mut cane.write_transaction());
+    assert_eq!(addresses(&cane), before);
+    assert_eq!(cane.into_inner(), (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn committing_is_like_writing() {
+    let cane = Cane::<Contiguous>::from_vec((0..10).collect());
+    let mut model = (0..10).collect::<Vec<_>>();
+
+    let mut transaction = cane.write_transaction();
+    assert_eq!(*transaction, model);
+    scribble(&mut transaction);
+    transaction.commit();
+    scribble(&mut model);
+
+    assert_eq!(cane.len(), model.len());
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.into_inner(), model);
+}
+
+#[test]
+fn aborting_keeps_segments() {
+    let cane = Cane::<Segmented>::from_vec((0..8).collect());
+    cane.extend(8..300);
+    let chunks = cane.chunk_count();
+    let before = addresses(&cane);
+
+    let mut transaction = cane.write_transaction();
+    scribble(&mut transaction);
+    drop(transaction);
+
+    // Where `write()` would have gone back to four chunks.
+    assert!(chunks > 4);
+    assert_eq!(cane.chunk_count(), chunks);
+    assert_eq!(addresses(&cane), before);
+
+    cane.write_transaction().commit();
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.into_inner(), (0..300).collect::<Vec<_>>());
+}
+
+#[test]
+fn appended_elements_are_copied() {
+    let cane = Cane::<Contiguous>::new();
+    cane.reserve(10);
+    cane.extend(0..10);
+
+    let transaction = cane.write_transaction();
+    assert_eq!(*transaction, (0..10).collect::<Vec<_>>());
+    drop(transaction);
+
+    cane.push(10);
+    assert_eq!(cane.into_inner(), (0..11).collect::<Vec<_>>());
+}
+
+#[test]
+fn failing_to_copy_leaves_everything_as_it_was() {
+    static FAIL: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Debug, PartialEq)]
+    struct Fussy(usize);
+
+    impl Clone for Fussy {
+        fn clone(&self) -> Self {
+            if FAIL.load(Ordering::Relaxed) && self.0 == 5 {
+                panic!("can't clone {}", self.0);
+            }
+            Fussy(self.0)
+        }
+    }
+
+    let cane = RawCandyCane::<RawRwLock, RawMutex, Fussy, 4>::from_vec((0..10).map(Fussy).collect());
+    FAIL.store(true, Ordering::Relaxed);
+    {
+        let _x = hushed_panic::hush_this_test();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(cane.write_transaction())));
+        assert!(result.is_err());
+    }
+    FAIL.store(false, Ordering::Relaxed);
+
+    assert_eq!(cane.into_inner(), (0..10).map(Fussy).collect::<Vec<_>>());
+}
+
+#[test]
+fn readers_never_see_aborted_edits() {
+    const ROUNDS: usize = if cfg!(miri) { 10 } else { 500 };
+    let cane = Cane::<Contiguous>::from_vec(vec![0; 64]);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let mut iter = cane.iter_streaming(..);
+                    let mut values = Vec::new();
+                    while let Some(&x) = iter.next() {
+                        values.push(x);
+                    }
+                    // Every commit sets every element to the same.
+                    assert_eq!(values.len(), 64);
+                    assert!(values.iter().all(|&x| x == values[0]), "{:?}", values);
+                }
+            });
+        }
+
+        for round in 1..=ROUNDS {
+            let mut transaction = cane.write_transaction();
+            transaction.iter_mut().for_each(|x| *x = round);
+            transaction.push(round);
+            if round % 2 == 0 {
+                transaction.pop();
+                transaction.commit();
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert_eq!(cane.into_inner(), vec![ROUNDS / 2 * 2; 64]);
+}
@@ -0,0 +1,116 @@
+use candy_cane::{RawCandyCane, Segmented};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 4>;
+
+#[test]
+fn check_then_upgrade() {
+    let cane = Cane::from_vec((0..10).collect());
+
+    let read = cane.upgradable_read();
+    let mut iter = read.iter_streaming(..);
+    let mut sum = 0;
+    while let Some(&x) = iter.next() {
+        sum += x;
+    }
+    drop(iter);
+    assert_eq!(read.len(), 10);
+
+    read.upgrade().push(sum);
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.into_inner(), (0..10).chain([45]).collect::<Vec<_>>());
+}
+
+#[test]
+fn upgrading_segmented() {
+    let cane = RawCandyCane::<RawRwLock, RawMutex, usize, 4, Segmented>::from_vec((0..8).collect());
+    cane.extend(8..100);
+
+    let read = cane.upgradable_read();
+    assert_eq!(read.len(), 100);
+    read.upgrade().retain(|x| x % 2 == 0);
+    assert_eq!(cane.chunk_count(), 4);
+    assert_eq!(cane.into_inner(), (0..100).step_by(2).collect::<Vec<_>>());
+}
+
+#[test]
+fn readers_dont_wait_for_it() {
+    let cane = Cane::from_vec((0..10).collect());
+    // Or pushing would need `write()`.
+    cane.reserve(1);
+
+    let read = cane.upgradable_read();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming(..);
+            while iter.next().is_some() {}
+            drop(iter);
+            cane.push(10);
+        });
+    });
+    assert_eq!(read.len(), 11);
+    drop(read);
+}
+
+#[test]
+fn writers_dont_get_in_first() {
+    let cane = Cane::from_vec(vec![0; 4]);
+
+    let read = cane.upgradable_read();
+    std::thread::scope(|s| {
+        s.spawn(|| cane.write().push(2));
+        // Give the writer time to start waiting.
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(read.len(), 4);
+        read.upgrade().push(1);
+    });
+    assert_eq!(cane.into_inner(), [0, 0, 0, 0, 1, 2]);
+}
+
+#[test]
+fn upgrading_waits_for_readers() {
+    let cane = Cane::from_vec(vec![0; 4]);
+    let started = Barrier::new(2);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming(..);
+            iter.next();
+            started.wait();
+            std::thread::sleep(Duration::from_millis(50));
+            done.store(true, Ordering::Relaxed);
+        });
+
+        let read = cane.upgradable_read();
+        started.wait();
+        let guard = read.upgrade();
+        assert!(done.load(Ordering::Relaxed));
+        drop(guard);
+    });
+}
+
+#[test]
+fn iterating_while_a_writer_waits() {
+    let cane = Cane::from_vec((0..10).collect());
+
+    std::thread::scope(|s| {
+        let read = cane.upgradable_read();
+        s.spawn(|| cane.write().clear());
+        std::thread::sleep(Duration::from_millis(50));
+
+        // Would wait for the writer, which waits for `read`, if it
+        // came from the cane instead.
+        let mut iter = read.iter_streaming(5..);
+        let mut visited = Vec::new();
+        while let Some(&x) = iter.next() {
+            visited.push(x);
+        }
+        visited.sort_unstable();
+        assert_eq!(visited, [5, 6, 7, 8, 9]);
+    });
+    assert!(cane.is_empty());
+}
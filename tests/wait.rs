@@ -0,0 +1,80 @@
+use candy_cane::{RawCandyCane, WaitStrategy};
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 2>;
+
+/// Holds both chunks, then releases the second one. Returns
+/// how much of the cane the waiting iterator had visited by
+/// the time the first chunk was released too.
+fn visited_once_second_released(wait: WaitStrategy) -> usize {
+    let cane = Cane::from_vec(vec![0; 16]);
+    cane.set_wait_strategy(wait);
+    let seen = AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        let mut first = cane.iter_streaming_mut(..8);
+        first.next();
+        let mut second = cane.iter_streaming_mut(8..);
+        second.next();
+
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming_mut(..);
+            while iter.next().is_some() {
+                seen.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(seen.load(Ordering::SeqCst), 0);
+
+        drop(second);
+        if wait != WaitStrategy::Block {
+            while seen.load(Ordering::SeqCst) < 8 {
+                std::thread::yield_now();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        seen.load(Ordering::SeqCst)
+    })
+}
+
+#[test]
+fn block_waits_on_one_chunk() {
+    assert_eq!(visited_once_second_released(WaitStrategy::Block), 0);
+}
+
+#[test]
+fn spin_takes_whichever_is_released() {
+    assert_eq!(visited_once_second_released(WaitStrategy::Spin), 8);
+}
+
+#[test]
+fn park_takes_whichever_is_released() {
+    assert_eq!(visited_once_second_released(WaitStrategy::Park), 8);
+}
+
+#[test]
+fn every_strategy_visits_everything() {
+    const THREADS: usize = 4;
+    const LEN: usize = if cfg!(miri) { 40 } else { 1000 };
+
+    for wait in [WaitStrategy::Block, WaitStrategy::Spin, WaitStrategy::Park] {
+        let cane = Cane::from_vec(vec![0; LEN]);
+        cane.set_wait_strategy(wait);
+
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let mut iter = cane.iter_streaming_mut(..);
+                    while let Some(x) = iter.next() {
+                        *x += 1;
+                    }
+                });
+            }
+        });
+
+        assert!(cane.into_inner().iter().all(|&x| x == THREADS), "{:?}", wait);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::common::visited;
+use candy_cane::iter::RangeError;
+use candy_cane::RawCandyCane;
+use parking_lot::{RawMutex, RawRwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Every chunk is two elements long.
+type Cane = RawCandyCane<RawRwLock, RawMutex, usize, 4>;
+
+#[test]
+fn writes_across_chunks() {
+    let cane = Cane::from_vec(vec![0; 8]);
+    let mut guard = cane.write_range(1..6);
+    assert_eq!(guard.len(), 5);
+    assert_eq!(guard.chunks(), 3);
+    guard.iter_mut().enumerate().for_each(|(i, x)| *x = i + 1);
+    drop(guard);
+
+    assert_eq!(visited(&cane), [0, 0, 0, 1, 2, 3, 4, 5]);
+    assert_eq!(*cane.write(), [0, 1, 2, 3, 4, 5, 0, 0]);
+}
+
+#[test]
+fn empty_and_invalid_ranges() {
+    let cane = Cane::from_vec((0..8).collect());
+    assert!(cane.write_range(3..3).is_empty());
+    assert_eq!(cane.write_range(3..3).chunks(), 0);
+    assert_eq!(
+        cane.try_write_range(5..9).err(),
+        Some(RangeError::EndOutOfBounds { end: 9, len: 8 })
+    );
+    assert!(Cane::new().write_range(..).is_empty());
+}
+
+#[test]
+fn other_chunks_can_be_iterated() {
+    let cane = Cane::from_vec((0..8).collect());
+    let guard = cane.write_range(0..3);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut iter = cane.iter_streaming(4..);
+            let mut sum = 0;
+            while let Some(x) = iter.next() {
+                sum += x;
+            }
+            assert_eq!(sum, 4 + 5 + 6 + 7);
+        });
+    });
+    drop(guard);
+}
+
+#[test]
+fn overlapping_iterators_wait() {
+    let cane = Cane::from_vec((0..8).collect());
+    let seen = AtomicUsize::new(0);
+    let mut guard = cane.write_range(2..4);
+
+    std::thread::scope(|s| {
+        let iterator = s.spawn(|| {
+            let mut iter = cane.iter_streaming(..);
+            let mut visited = Vec::new();
+            while let Some(&x) = iter.next() {
+                visited.push(x);
+                seen.fetch_add(1, Ordering::SeqCst);
+            }
+            visited
+        });
+
+        // Everything but our chunk gets visited first.
+        while seen.load(Ordering::SeqCst) < 6 {
+            std::thread::yield_now();
+        }
+        guard.copy_from_slice(&[20, 30]);
+        drop(guard);
+
+        let mut visited = iterator.join().unwrap();
+        assert_eq!(visited[6..], [20, 30]);
+        visited.sort_unstable();
+        assert_eq!(visited, [0, 1, 4, 5, 6, 7, 20, 30]);
+    });
+}
+
+#[test]
+fn guards_over_different_threads() {
+    let cane = Cane::from_vec(vec![0; 64]);
+
+    std::thread::scope(|s| {
+        for thread in 0..4 {
+            let cane = &cane;
+            s.spawn(move || {
+                for round in 0..100 {
+                    let start = (thread * 7 + round) % 48;
+                    cane.write_range(start..start + 16).iter_mut().for_each(|x| *x += 1);
+                }
+            });
+        }
+    });
+
+    assert_eq!(cane.write().iter().sum::<usize>(), 4 * 100 * 16);
+}